@@ -0,0 +1,19 @@
+//! Builds a few lists, prints them, and collects the ones no longer needed.
+
+use slip_rs::{grammar::{integer, list, Pair}, memory::{MemPtr, Memory}, printer::print};
+
+fn main() -> anyhow::Result<()> {
+    let mut data: [u64 ; 1000] = [ 0 ; 1000 ];
+    let mem = Memory::new(&mut data);
+
+    let numbers = (1..=3).map(|n| <MemPtr>::fixnum(n).unwrap());
+    let mut kept = list(&mem, numbers)?;
+    let dotted = Pair::new(&mem, kept.clone(), integer(&mem, i64::MAX)?)?;
+    println!("{}", print(&kept));
+    println!("{}", print(&dotted.upcast()));
+
+    println!("{} cells in use", mem.used());
+    mem.collect(&mut kept);
+    println!("{} cells in use after collecting, still {}", mem.used(), print(&kept));
+    Ok(())
+}
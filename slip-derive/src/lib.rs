@@ -0,0 +1,93 @@
+//! Derive macros for the memory chunks of slip-rs.
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, LitInt, Type};
+
+/// Returns the fields of a struct with named fields
+fn named_fields(input: &DeriveInput) -> Result<&syn::FieldsNamed, Error> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields),
+            fields => Err(Error::new(fields.span(), "memory chunks must have named fields")),
+        },
+        _ => Err(Error::new(input.span(), "memory chunks must be structs")),
+    }
+}
+
+/// Returns true if the type is (a path to) `Header`
+fn is_header(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.path.segments.last().is_some_and(|s| s.ident == "Header"))
+}
+
+/// Derives `ChunkContent` for a struct whose first field is its `Header`.
+///
+/// The number of cells is computed from the types of the remaining fields, 
+/// and checked at compile time to be a whole number of cells without padding.
+/// The tag is given with the `#[tag(...)]` attribute:
+///
+/// ```ignore
+/// #[derive(ChunkContent, Trace)]
+/// #[tag(1)]
+/// struct Pair<'t> {
+///     _hdr: Header,
+///     car: MemPtr<'t>,
+///     cdr: MemPtr<'t>
+/// }
+/// ```
+#[proc_macro_derive(ChunkContent, attributes(tag))]
+pub fn derive_chunk_content(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_chunk_content(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_chunk_content(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = named_fields(input)?;
+    let mut fields = fields.named.iter();
+    if !fields.next().is_some_and(|field| is_header(&field.ty)) {
+        return Err(Error::new(input.ident.span(), "the first field of a memory chunk must be its `Header`"));
+    }
+    let tys = fields.map(|field| &field.ty);
+    let tag: LitInt = input.attrs.iter()
+        .find(|attr| attr.path().is_ident("tag"))
+        .ok_or_else(|| Error::new(input.ident.span(), "missing `#[tag(...)]` attribute"))?
+        .parse_args()?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::slip_rs::memory::ChunkContent for #name #ty_generics #where_clause {
+            fn size() -> isize {
+                const {
+                    let fields = 0 #(+ ::core::mem::size_of::<#tys>())*;
+                    assert!(fields % ::core::mem::size_of::<u64>() == 0, 
+                        "the fields of a memory chunk must fill whole cells");
+                    assert!(fields + ::core::mem::size_of::<::slip_rs::memory::Header>() == ::core::mem::size_of::<Self>(), 
+                        "the fields of a memory chunk must not be padded");
+                    (fields / ::core::mem::size_of::<u64>()) as isize
+                }
+            }
+
+            fn tag() -> usize { #tag }
+        }
+    })
+}
+
+/// Derives `Trace` by tracing every field of the struct
+#[proc_macro_derive(Trace)]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_trace(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_trace(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = named_fields(input)?.named.iter().map(|field| &field.ident);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::slip_rs::memory::Trace for #name #ty_generics #where_clause {
+            fn trace(&mut self, visitor: &mut impl FnMut(&mut ::slip_rs::memory::MemPtr<'_>)) {
+                #(::slip_rs::memory::Trace::trace(&mut self.#fields, visitor);)*
+            }
+        }
+    })
+}
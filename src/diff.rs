@@ -0,0 +1,208 @@
+//! Structural comparison of values, explaining where they differ
+
+use core::fmt;
+
+use alloc::vec::Vec;
+
+use crate::{grammar::{Bytevector, Pair, Str, Vector}, memory::{Bytes, MemPtr}, numeric::Numeric, printer::Printer};
+
+/// Step from a value to one of its components
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Car,
+    Cdr,
+    /// Slot of a vector
+    Index(usize)
+}
+
+/// The first components in which two values differ, see `explain_inequality`
+#[derive(Debug)]
+pub struct Difference<'t> {
+    /// Steps from the compared values to the components, outermost first
+    pub path: Vec<Step>,
+    pub left: MemPtr<'t>,
+    pub right: MemPtr<'t>
+}
+
+impl fmt::Display for Difference<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not {} at ", Printer(&self.left), Printer(&self.right))?;
+        // written as the accessors that reach the components, innermost last
+        for step in self.path.iter().rev() {
+            match step {
+                Step::Car => write!(f, "(car ")?,
+                Step::Cdr => write!(f, "(cdr ")?,
+                Step::Index(_) => write!(f, "(vector-ref ")?
+            }
+        }
+        write!(f, "_")?;
+        self.path.iter().try_for_each(|step| match step {
+            Step::Index(i) => write!(f, " {})", i),
+            _ => write!(f, ")")
+        })
+    }
+}
+
+/// Returns true if the values are the same, or are numbers
+/// of the same exactness and value. Inexact numbers are compared
+/// by their bits, so `0.0` is not `-0.0` and a NaN is itself.
+pub fn eqv(a: &MemPtr<'_>, b: &MemPtr<'_>) -> bool {
+    a == b || match (a.as_numeric(), b.as_numeric()) {
+        (Some(Numeric::Float(x)), Some(Numeric::Float(y))) => x.to_bits() == y.to_bits(),
+        (Some(x), Some(y)) => x == y,
+        _ => false
+    }
+}
+
+/// Returns true if the values are the same, or are pairs, vectors, strings,
+/// bytevectors or bytes
+/// with equal contents, or are numbers of the same exactness and value.
+/// The values must not be circular.
+pub fn equal(a: &MemPtr<'_>, b: &MemPtr<'_>) -> bool {
+    explain_inequality(a, b).is_none()
+}
+
+/// Returns the first components in which the values differ, in the order
+/// `car` before `cdr`, or nothing if they are `equal`.
+pub fn explain_inequality<'t>(a: &MemPtr<'t>, b: &MemPtr<'t>) -> Option<Difference<'t>> {
+    let mut path = Vec::new();
+    let found = differ(a, b, &mut path)?;
+    Some(Difference { path, left: found.0, right: found.1 })
+}
+
+/// Returns the first differing components, extending `path` to them,
+/// leaves `path` as it was if there are none
+fn differ<'t>(a: &MemPtr<'t>, b: &MemPtr<'t>, path: &mut Vec<Step>) -> Option<(MemPtr<'t>, MemPtr<'t>)> {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    let depth = path.len();
+    // follows the `cdr`s in a loop, so long lists do not exhaust the stack
+    loop {
+        if let (Ok(x), Ok(y)) = (a.downcast::<Vector>(), b.downcast::<Vector>()) {
+            let found = differ_slots(&x, &y, path);
+            if found.is_none() {
+                path.truncate(depth);
+            }
+            return found.map(|found| found.unwrap_or((a, b)));
+        }
+        let (Ok(x), Ok(y)) = (a.downcast::<Pair>(), b.downcast::<Pair>()) else {
+            if a == b || leaves_equal(&a, &b) {
+                path.truncate(depth);
+                return None;
+            }
+            return Some((a, b));
+        };
+        if x == y {
+            path.truncate(depth);
+            return None;
+        }
+        path.push(Step::Car);
+        if let Some(found) = differ(&x.car, &y.car, path) {
+            return Some(found);
+        }
+        path.pop();
+        path.push(Step::Cdr);
+        (a, b) = (x.cdr.clone(), y.cdr.clone());
+    }
+}
+
+/// Returns the first differing components of two vectors, or nothing
+/// to report the vectors themselves if their lengths differ
+fn differ_slots<'t>(x: &MemPtr<'t, Vector>, y: &MemPtr<'t, Vector>, path: &mut Vec<Step>) -> Option<Option<(MemPtr<'t>, MemPtr<'t>)>> {
+    if x == y {
+        return None;
+    }
+    let (Ok(xs), Ok(ys)) = (x.slots(), y.slots()) else { return Some(None) };
+    if xs.len() != ys.len() {
+        return Some(None);
+    }
+    for (i, (a, b)) in xs.iter().zip(ys).enumerate() {
+        match (a.get(), b.get()) {
+            (Some(a), Some(b)) => {
+                path.push(Step::Index(i));
+                if let Some(found) = differ(a, b, path) {
+                    return Some(Some(found));
+                }
+                path.pop();
+            },
+            (None, None) => {},
+            // an empty slot is only equal to another empty slot
+            _ => return Some(None)
+        }
+    }
+    None
+}
+
+/// Compares values that are not both pairs
+fn leaves_equal(a: &MemPtr<'_>, b: &MemPtr<'_>) -> bool {
+    if eqv(a, b) {
+        return true;
+    }
+    if let (Ok(x), Ok(y)) = (a.downcast::<Str>(), b.downcast::<Str>()) {
+        return x.as_str().ok() == y.as_str().ok();
+    }
+    if let (Ok(x), Ok(y)) = (a.downcast::<Bytevector>(), b.downcast::<Bytevector>()) {
+        return x.bytes().ok() == y.bytes().ok();
+    }
+    if let (Ok(x), Ok(y)) = (a.downcast::<Bytes>(), b.downcast::<Bytes>()) {
+        return x.as_bytes().ok() == y.as_bytes().ok();
+    }
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::grammar::{integer, list, Flonum, Symbol};
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_explain_inequality() {
+        let mut data: [u64 ; 500] = [ 0 ; 500 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let foo = Symbol::new(&mem, "foo").unwrap().upcast();
+        let make = |last| {
+            let inner = list(&mem, [Str::new(&mem, "bar").unwrap().upcast(), integer(&mem, i64::MAX).unwrap()]).unwrap();
+            list(&mem, [foo.clone(), inner, last]).unwrap()
+        };
+        let a = make(n(1));
+        assert!(equal(&a, &make(n(1))) && equal(&a, &a) && equal(&MemPtr::null(), &MemPtr::null()));
+
+        let difference = explain_inequality(&a, &make(n(2))).unwrap();
+        assert!(difference.path == [Step::Cdr, Step::Cdr, Step::Car]);
+        assert!(difference.left == n(1) && difference.right == n(2));
+        assert!(difference.to_string() == "1 is not 2 at (car (cdr (cdr _)))");
+
+        // exactness matters, and lists of different lengths differ in a tail
+        let float = Flonum::new(&mem, 1.0).unwrap().upcast();
+        assert!(explain_inequality(&n(1), &float).unwrap().to_string() == "1 is not 1.0 at _");
+        let shorter = list(&mem, [foo.clone()]).unwrap();
+        let difference = explain_inequality(&a, &shorter).unwrap();
+        assert!(difference.path == [Step::Cdr] && difference.right.is_null());
+        assert!(!equal(&foo, &Str::new(&mem, "foo").unwrap().upcast()));
+        assert!(equal(&Bytevector::new(&mem, b"ab").unwrap().upcast(), &Bytevector::new(&mem, b"ab").unwrap().upcast()));
+
+        // vectors are compared slot by slot
+        let vector = |last| {
+            let v = Vector::make(&mem, 2, Some(n(0))).unwrap();
+            v.set(1, last).unwrap();
+            v.upcast()
+        };
+        assert!(equal(&vector(make(n(1))), &vector(make(n(1)))));
+        let difference = explain_inequality(&vector(make(n(1))), &vector(make(n(2)))).unwrap();
+        assert!(difference.to_string() == "1 is not 2 at (car (cdr (cdr (vector-ref _ 1))))");
+        let empty = Vector::make(&mem, 2, None).unwrap().upcast();
+        assert!(explain_inequality(&vector(n(0)), &empty).unwrap().path.is_empty());
+        assert!(!equal(&empty, &Vector::make(&mem, 3, None).unwrap().upcast()));
+
+        // equal vectors in a tail leave no steps behind
+        let tail = |last| {
+            let inner = Pair::new(&mem, n(1), vector(n(2))).unwrap().upcast();
+            list(&mem, [inner, last]).unwrap()
+        };
+        let difference = explain_inequality(&tail(n(3)), &tail(n(4))).unwrap();
+        assert!(difference.path == [Step::Cdr, Step::Car]);
+    }
+}
@@ -0,0 +1,1507 @@
+//! The values of the language, as they are laid out in memory
+
+use alloc::vec::Vec;
+
+use anyhow::{anyhow, Result};
+
+use crate::{memory::{Backing, ByteChunk, Cell, ChunkContent, Header, MemPtr, Memory, Object, Trace}, numeric::Numeric};
+
+mod bignum;
+mod condition;
+mod convert;
+mod environment;
+mod hashtable;
+mod port;
+mod record;
+mod syntax;
+mod value;
+
+pub use bignum::{BigInt, Bignum};
+pub use condition::{Condition, ConditionKind};
+pub use convert::IntoValue;
+pub use environment::Environment;
+pub use hashtable::{Comparator, HashTable};
+pub use port::{Port, Stream};
+pub use record::{Record, RecordType};
+pub use syntax::{strip, Syntax};
+pub use value::Value;
+
+/// A pair of values, the building block of lists
+#[derive(ChunkContent, Trace)]
+#[tag(1)]
+pub struct Pair<'t> {
+    _hdr: Header,
+    pub car: MemPtr<'t>,
+    pub cdr: MemPtr<'t>
+}
+
+impl<'t> Pair<'t> {
+    /// Allocates a pair holding the given values
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, car: MemPtr<'t>, cdr: MemPtr<'t>) -> Result<MemPtr<'t, Pair<'t>>> {
+        mem.new_object::<Pair>((car, cdr))
+    }
+}
+
+impl<'t> Object for Pair<'t> {
+    type Init = (MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (car, cdr): Self::Init) -> Self {
+        Pair { _hdr: hdr, car, cdr }
+    }
+}
+
+impl<'t> MemPtr<'t> {
+    /// Returns the first element of the pair, fails if the value is not a pair
+    pub fn car(&self) -> Result<MemPtr<'t>> {
+        Ok(self.downcast::<Pair>()?.car.clone())
+    }
+
+    /// Returns the second element of the pair, fails if the value is not a pair
+    pub fn cdr(&self) -> Result<MemPtr<'t>> {
+        Ok(self.downcast::<Pair>()?.cdr.clone())
+    }
+
+    /// Same as `car`, but only checked in debug builds
+    ///
+    /// # Safety
+    ///
+    /// The value must be a pair.
+    pub unsafe fn car_unchecked(&self) -> MemPtr<'t> {
+        self.downcast_unchecked::<Pair>().car.clone()
+    }
+
+    /// Same as `cdr`, but only checked in debug builds
+    ///
+    /// # Safety
+    ///
+    /// The value must be a pair.
+    pub unsafe fn cdr_unchecked(&self) -> MemPtr<'t> {
+        self.downcast_unchecked::<Pair>().cdr.clone()
+    }
+}
+
+impl<'t> MemPtr<'t, Pair<'t>> {
+    /// Iterates over the elements of the list starting at the pair
+    pub fn iter(&self) -> ListIter<'t> {
+        ListIter::new(self.clone().upcast())
+    }
+}
+
+/// Iterator over the elements of a list. It ends with an error if the list
+/// is not terminated by the empty list, or if it is circular.
+pub struct ListIter<'t> {
+    /// The rest of the list
+    current: MemPtr<'t>,
+    /// Moves at half the speed of `current`, 
+    /// the list is circular if they ever meet
+    slow: MemPtr<'t>,
+    steps: usize,
+    done: bool
+}
+
+impl<'t> ListIter<'t> {
+    /// Iterates over the elements of the given list, which may be empty
+    pub fn new(list: MemPtr<'t>) -> ListIter<'t> {
+        ListIter { current: list.clone(), slow: list, steps: 0, done: false }
+    }
+}
+
+impl<'t> Iterator for ListIter<'t> {
+    type Item = Result<MemPtr<'t>>;
+
+    fn next(&mut self) -> Option<Result<MemPtr<'t>>> {
+        if self.done || self.current.is_null() {
+            return None;
+        }
+        let Ok(pair) = self.current.downcast::<Pair>() else {
+            self.done = true;
+            return Some(Err(anyhow!("improper list, ending in {:?}", self.current)));
+        };
+        self.current = pair.cdr.clone();
+        self.steps += 1;
+        if self.steps.is_multiple_of(2) {
+            self.slow = unsafe {
+                // SAFETY: `slow` trails `current`, over pairs it went through
+                self.slow.cdr_unchecked()
+            };
+            if self.slow == self.current {
+                self.done = true;
+                return Some(Err(anyhow!("circular list")));
+            }
+        }
+        Some(Ok(pair.car.clone()))
+    }
+}
+
+/// An integer too large to be a fixnum
+#[derive(ChunkContent)]
+#[tag(2)]
+pub struct Number {
+    _hdr: Header,
+    pub n: i64
+}
+
+/// A name, such as that of a variable. Symbols made by `Symbol::new` are
+/// interned in their memory, so two of them with the same name are the same
+/// pointer. Those made by `Symbol::fresh` are not, and are written `#{name}`.
+#[derive(ChunkContent, Trace)]
+#[tag(3)]
+pub struct Symbol<'t> {
+    _hdr: Header,
+    /// `Bytes` chunk holding the name
+    name: MemPtr<'t>,
+    /// Whether the symbol is interned
+    interned: u64
+}
+
+impl<'t> Symbol<'t> {
+    /// Returns the symbol with the given name, 
+    /// allocating it the first time it is asked for
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, name: &str) -> Result<MemPtr<'t, Symbol<'t>>> {
+        mem.intern(name.as_bytes(), |mem| {
+            let bytes = mem.allocate_bytes(name.len())?;
+            bytes.as_bytes_mut()?.copy_from_slice(name.as_bytes());
+            Ok(mem.new_object::<Symbol>((bytes, true))?.upcast())
+        })?.downcast()
+    }
+
+    /// Allocates an uninterned symbol, named by the prefix followed by a number.
+    /// It is only ever the same as itself, never a symbol that was read or made
+    /// by `new`, even one with the same name, so it can name the variables
+    /// introduced by macros and the temporaries of the compiler.
+    /// Unlike interned symbols, it is freed once it is no longer reachable.
+    pub fn fresh<B: Backing>(mem: &'t Memory<'t, B>, prefix: &str) -> Result<MemPtr<'t, Symbol<'t>>> {
+        let name = alloc::format!("{}{}", prefix, FRESH.fetch_add(1, core::sync::atomic::Ordering::Relaxed));
+        let bytes = mem.allocate_bytes(name.len())?;
+        bytes.as_bytes_mut()?.copy_from_slice(name.as_bytes());
+        mem.new_object::<Symbol>((bytes, false))
+    }
+
+    /// Returns the name of the symbol
+    pub fn name(&self) -> Result<&str> {
+        Ok(core::str::from_utf8(self.name.as_bytes()?)?)
+    }
+
+    /// Returns false for the symbols made by `fresh`
+    pub fn is_interned(&self) -> bool {
+        self.interned != 0
+    }
+}
+
+/// Number of the next symbol made by `Symbol::fresh`
+static FRESH: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(1);
+
+impl<'t> Object for Symbol<'t> {
+    /// The `Bytes` chunk holding the name, and whether the symbol is interned
+    type Init = (MemPtr<'t>, bool);
+
+    fn init(hdr: Header, (name, interned): Self::Init) -> Self {
+        Symbol { _hdr: hdr, name, interned: interned as u64 }
+    }
+}
+
+/// Prefixed to the names keywords are interned under, a byte that never
+/// occurs in UTF-8, so they never share a chunk with the symbols
+const KEYWORD_NAMESPACE: u8 = 0xff;
+
+/// A self-evaluating name, written `#:name`, for keyword arguments and
+/// configuration data. Keywords are interned like symbols, but apart
+/// from them, so a keyword is never the symbol of the same name.
+#[derive(ChunkContent, Trace)]
+#[tag(21)]
+pub struct Keyword<'t> {
+    _hdr: Header,
+    /// `Bytes` chunk holding the name, without `#:`
+    name: MemPtr<'t>
+}
+
+impl<'t> Keyword<'t> {
+    /// Returns the keyword with the given name (without `#:`),
+    /// allocating it the first time it is asked for
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, name: &str) -> Result<MemPtr<'t, Keyword<'t>>> {
+        let key = [&[KEYWORD_NAMESPACE], name.as_bytes()].concat();
+        mem.intern(&key, |mem| {
+            let bytes = mem.allocate_bytes(name.len())?;
+            bytes.as_bytes_mut()?.copy_from_slice(name.as_bytes());
+            Ok(mem.new_object::<Keyword>(bytes)?.upcast())
+        })?.downcast()
+    }
+
+    /// Returns the name of the keyword, without `#:`
+    pub fn name(&self) -> Result<&str> {
+        Ok(core::str::from_utf8(self.name.as_bytes()?)?)
+    }
+}
+
+impl<'t> Object for Keyword<'t> {
+    /// The `Bytes` chunk holding the name
+    type Init = MemPtr<'t>;
+
+    fn init(hdr: Header, name: Self::Init) -> Self {
+        Keyword { _hdr: hdr, name }
+    }
+}
+
+/// A string of characters, of which the UTF-8 encoding is
+/// stored in the cells following its length
+#[derive(ChunkContent)]
+#[tag(4)]
+pub struct Str {
+    _hdr: Header,
+    /// Length of the string in bytes
+    len: u64
+}
+
+impl Str {
+    /// Allocates a copy of the given string
+    pub fn new<'t, B: Backing>(mem: &'t Memory<'t, B>, s: &str) -> Result<MemPtr<'t, Str>> {
+        let ptr = mem.allocate_byte_chunk::<Str>(s.len())?;
+        ptr.tail_bytes_mut::<Str>()?.copy_from_slice(s.as_bytes());
+        ptr.downcast()
+    }
+
+    /// Length of the string in bytes
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if the string is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl ByteChunk for Str {
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+
+    fn set_byte_len(&mut self, len: usize) {
+        self.len = len as u64;
+    }
+}
+
+impl<'t> MemPtr<'t, Str> {
+    /// Returns the characters of the string
+    pub fn as_str(&self) -> Result<&str> {
+        Ok(core::str::from_utf8(self.tail_bytes::<Str>()?)?)
+    }
+}
+
+/// A sequence of bytes of a fixed length, stored in
+/// the cells following its length
+#[derive(ChunkContent)]
+#[tag(9)]
+pub struct Bytevector {
+    _hdr: Header,
+    /// Number of bytes
+    len: u64
+}
+
+impl Bytevector {
+    /// Allocates a bytevector of `len` bytes, all set to `fill`
+    pub fn make<'t, B: Backing>(mem: &'t Memory<'t, B>, len: usize, fill: u8) -> Result<MemPtr<'t, Bytevector>> {
+        let ptr = mem.allocate_byte_chunk::<Bytevector>(len)?.downcast::<Bytevector>()?;
+        ptr.bytes_mut()?.fill(fill);
+        Ok(ptr)
+    }
+
+    /// Allocates a copy of the given bytes
+    pub fn new<'t, B: Backing>(mem: &'t Memory<'t, B>, bytes: &[u8]) -> Result<MemPtr<'t, Bytevector>> {
+        let ptr = Bytevector::make(mem, bytes.len(), 0)?;
+        ptr.bytes_mut()?.copy_from_slice(bytes);
+        Ok(ptr)
+    }
+
+    /// Number of bytes
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if there are no bytes
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl ByteChunk for Bytevector {
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+
+    fn set_byte_len(&mut self, len: usize) {
+        self.len = len as u64;
+    }
+}
+
+impl MemPtr<'_, Bytevector> {
+    /// Returns the bytes of the bytevector
+    pub fn bytes(&self) -> Result<&[u8]> {
+        self.tail_bytes::<Bytevector>()
+    }
+
+    /// Same as `bytes` but returns an exclusive mutable slice,
+    /// fails if the bytevector is frozen
+    #[allow(clippy::mut_from_ref)]
+    pub fn bytes_mut(&self) -> Result<&mut [u8]> {
+        self.tail_bytes_mut::<Bytevector>()
+    }
+
+    /// Returns the byte at the given index, fails if it is out of range
+    pub fn get(&self, index: usize) -> Result<u8> {
+        self.bytes()?.get(index).copied()
+            .ok_or_else(|| anyhow!("index {} out of range for a bytevector of length {}", index, self.len()))
+    }
+
+    /// Sets the byte at the given index, fails if it is out of range
+    pub fn set(&self, index: usize, byte: u8) -> Result<()> {
+        let len = self.len();
+        *self.bytes_mut()?.get_mut(index)
+            .ok_or_else(|| anyhow!("index {} out of range for a bytevector of length {}", index, len))? = byte;
+        Ok(())
+    }
+}
+
+/// A sequence of values of a fixed length, held in the
+/// slots following its header, which may be empty
+#[derive(ChunkContent)]
+#[tag(8)]
+pub struct Vector {
+    _hdr: Header,
+    /// Number of slots
+    len: u64
+}
+
+impl Vector {
+    /// Allocates a vector of `len` slots, all holding `fill`
+    /// or all empty if there is none
+    pub fn make<'t, B: Backing>(mem: &'t Memory<'t, B>, len: usize, fill: Option<MemPtr<'t>>) -> Result<MemPtr<'t, Vector>> {
+        let ptr = mem.allocate::<Vector>(isize::try_from(len)?)?;
+        ptr.modify::<Vector>(|vector| vector.len = len as u64);
+        let ptr = ptr.downcast::<Vector>()?;
+        for slot in ptr.slots_mut()? {
+            *slot = fill.clone().map(Cell::new).unwrap_or_default();
+        }
+        Ok(ptr)
+    }
+
+    /// Number of slots of the vector
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if the vector has no slots
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Trace for Vector {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        let len = self.len();
+        let slots = unsafe {
+            // SAFETY: the slots follow the fixed cells, `len` of them
+            core::slice::from_raw_parts_mut((self as *mut Vector).add(1) as *mut Cell<'_>, len)
+        };
+        slots.trace(visitor)
+    }
+}
+
+impl<'t> MemPtr<'t, Vector> {
+    /// Returns the slots of the vector
+    pub fn slots(&self) -> Result<&[Cell<'t>]> {
+        let cells = self.tail_slice::<Vector>()?;
+        Ok(unsafe {
+            // SAFETY: a cell has the layout of a pointer, which is a single cell
+            core::slice::from_raw_parts(cells.as_ptr() as *const Cell<'t>, cells.len())
+        })
+    }
+
+    /// Same as `slots` but returns an exclusive mutable slice,
+    /// fails if the vector is frozen
+    #[allow(clippy::mut_from_ref)]
+    pub fn slots_mut(&self) -> Result<&mut [Cell<'t>]> {
+        let cells = self.tail_slice_mut::<Vector>()?;
+        Ok(unsafe {
+            // SAFETY: see `slots`
+            core::slice::from_raw_parts_mut(cells.as_mut_ptr() as *mut Cell<'t>, cells.len())
+        })
+    }
+
+    /// Same as `ref_`, but only checked in debug builds
+    ///
+    /// # Safety
+    ///
+    /// The index must be in range, and its slot not empty.
+    pub unsafe fn ref_unchecked(&self, index: usize) -> MemPtr<'t> {
+        debug_assert!(self.ref_(index).is_ok(), "unchecked reference to slot {} of a vector of length {}", index, self.len());
+        let slots = (&**self as *const Vector).add(1) as *const Cell<'t>;
+        (*slots.add(index)).get().unwrap_unchecked().clone()
+    }
+
+    /// Returns the value in the given slot, fails if
+    /// it is out of range or the slot is empty
+    pub fn ref_(&self, index: usize) -> Result<MemPtr<'t>> {
+        let slot = self.slots()?.get(index)
+            .ok_or_else(|| anyhow!("index {} out of range for a vector of length {}", index, self.len()))?;
+        slot.get().cloned().ok_or_else(|| anyhow!("slot {} of the vector is empty", index))
+    }
+
+    /// Stores the value in the given slot, fails if it is out of range
+    pub fn set(&self, index: usize, value: MemPtr<'t>) -> Result<()> {
+        let len = self.len();
+        self.slots_mut()?.get_mut(index)
+            .ok_or_else(|| anyhow!("index {} out of range for a vector of length {}", index, len))?
+            .set(value);
+        Ok(())
+    }
+}
+
+/// Number of arguments a procedure accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arity {
+    /// Number of required arguments
+    pub required: usize,
+    /// Whether any further arguments are accepted, as a list
+    pub rest: bool
+}
+
+impl Arity {
+    /// Returns true if the procedure can be called with `n` arguments
+    pub fn accepts(&self, n: usize) -> bool {
+        n == self.required || (self.rest && n > self.required)
+    }
+}
+
+/// A user-defined procedure: the code of its body,
+/// and the environment it was created in
+#[derive(ChunkContent, Trace)]
+#[tag(11)]
+pub struct Closure<'t> {
+    _hdr: Header,
+    /// The body, as its expressions or as its compiled `Code`
+    pub code: MemPtr<'t>,
+    /// The `Environment` the body is evaluated in, extended with the arguments
+    pub env: MemPtr<'t>,
+    /// See `Arity::required`
+    required: u64,
+    /// See `Arity::rest`
+    rest: u64
+}
+
+impl<'t> Closure<'t> {
+    /// Allocates a closure over the given code and environment
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, code: MemPtr<'t>, env: MemPtr<'t>, arity: Arity) -> Result<MemPtr<'t, Closure<'t>>> {
+        mem.new_object::<Closure>((code, env, arity))
+    }
+
+    pub fn arity(&self) -> Arity {
+        Arity { required: self.required as usize, rest: self.rest != 0 }
+    }
+}
+
+impl<'t> Object for Closure<'t> {
+    type Init = (MemPtr<'t>, MemPtr<'t>, Arity);
+
+    fn init(hdr: Header, (code, env, arity): Self::Init) -> Self {
+        Closure { _hdr: hdr, code, env, required: arity.required as u64, rest: arity.rest as u64 }
+    }
+}
+
+/// A compiled procedure body: its bytecode, and the constants it refers to 
+/// by index, which are traced and updated by the collector along with it
+#[derive(ChunkContent, Trace)]
+#[tag(14)]
+pub struct Code<'t> {
+    _hdr: Header,
+    /// Name of the procedure for error messages, a `Symbol` or `#f`
+    pub name: MemPtr<'t>,
+    /// `Bytevector` of the instructions
+    bytecode: MemPtr<'t>,
+    /// `Vector` of the constant pool
+    constants: MemPtr<'t>
+}
+
+impl<'t> Code<'t> {
+    /// Allocates a code object holding copies of the bytecode and constants
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, name: MemPtr<'t>, bytecode: &[u8], constants: &[MemPtr<'t>]) -> Result<MemPtr<'t, Code<'t>>> {
+        let bytecode = Bytevector::new(mem, bytecode)?.upcast();
+        let pool = Vector::make(mem, constants.len(), None)?;
+        for (slot, constant) in pool.slots_mut()?.iter_mut().zip(constants) {
+            slot.set(constant.clone());
+        }
+        mem.new_object::<Code>((name, bytecode, pool.upcast()))
+    }
+}
+
+impl<'t> Object for Code<'t> {
+    /// The name, the `Bytevector` of the bytecode and the `Vector` of constants
+    type Init = (MemPtr<'t>, MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (name, bytecode, constants): Self::Init) -> Self {
+        Code { _hdr: hdr, name, bytecode, constants }
+    }
+}
+
+impl<'t> MemPtr<'t, Code<'t>> {
+    /// Returns the instructions
+    pub fn bytecode(&self) -> Result<&[u8]> {
+        let bytecode = self.bytecode.downcast::<Bytevector>()?;
+        let bytes = bytecode.bytes()?;
+        Ok(unsafe {
+            // SAFETY: the bytes live in the memory as long as this code object,
+            // not just as long as the pointer to the bytevector
+            core::slice::from_raw_parts(bytes.as_ptr(), bytes.len())
+        })
+    }
+
+    /// Number of constants in the pool
+    pub fn constants(&self) -> Result<usize> {
+        Ok(self.constants.downcast::<Vector>()?.len())
+    }
+
+    /// Returns the constant at the given index of the pool
+    pub fn constant(&self, index: usize) -> Result<MemPtr<'t>> {
+        self.constants.downcast::<Vector>()?.ref_(index)
+    }
+}
+
+/// A captured continuation: a copy of the segment of the evaluator's stack 
+/// that was live when it was captured, on top of the continuation that
+/// was captured before, so that the segments below are shared
+#[derive(ChunkContent, Trace)]
+#[tag(13)]
+pub struct Continuation<'t> {
+    _hdr: Header,
+    /// The `Continuation` below the segment, or the empty list at the bottom
+    pub next: MemPtr<'t>,
+    /// `Vector` of the values of the segment, bottom first
+    segment: MemPtr<'t>
+}
+
+impl<'t> Continuation<'t> {
+    /// Allocates a continuation holding a copy of the given stack segment
+    pub fn capture<B: Backing>(mem: &'t Memory<'t, B>, segment: &[MemPtr<'t>], next: MemPtr<'t>) -> Result<MemPtr<'t, Continuation<'t>>> {
+        let vector = Vector::make(mem, segment.len(), None)?;
+        for (slot, value) in vector.slots_mut()?.iter_mut().zip(segment) {
+            slot.set(value.clone());
+        }
+        mem.new_object::<Continuation>((next, vector.upcast()))
+    }
+}
+
+impl<'t> Object for Continuation<'t> {
+    /// The next continuation and the `Vector` of the segment
+    type Init = (MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (next, segment): Self::Init) -> Self {
+        Continuation { _hdr: hdr, next, segment }
+    }
+}
+
+impl<'t> MemPtr<'t, Continuation<'t>> {
+    /// Returns the values of the segment captured by this continuation, bottom first
+    pub fn segment(&self) -> Result<Vec<MemPtr<'t>>> {
+        let vector = self.segment.downcast::<Vector>()?;
+        (0..vector.len()).map(|i| vector.ref_(i)).collect()
+    }
+
+    /// Returns the whole stack the continuation resumes with, bottom first
+    pub fn stack(&self) -> Result<Vec<MemPtr<'t>>> {
+        let mut segments = alloc::vec![self.clone()];
+        while !segments.last().unwrap().next.is_null() {
+            let next = segments.last().unwrap().next.downcast::<Continuation>()?;
+            segments.push(next);
+        }
+        let mut stack = Vec::new();
+        for continuation in segments.iter().rev() {
+            stack.extend(continuation.segment()?);
+        }
+        Ok(stack)
+    }
+}
+
+/// A delayed computation: a thunk not called yet, or the result it
+/// returned, which is memoized so the thunk is called at most once
+#[derive(ChunkContent, Trace)]
+#[tag(15)]
+pub struct Promise<'t> {
+    _hdr: Header,
+    /// The thunk while the promise is not forced, its result afterwards
+    value: MemPtr<'t>,
+    forced: u64
+}
+
+impl<'t> Promise<'t> {
+    /// Allocates a promise that calls the thunk when it is forced, as `delay`
+    pub fn delay<B: Backing>(mem: &'t Memory<'t, B>, thunk: MemPtr<'t>) -> Result<MemPtr<'t, Promise<'t>>> {
+        mem.new_object::<Promise>((thunk, false))
+    }
+
+    /// Allocates a promise that is already forced to the value, as `make-promise`
+    pub fn ready<B: Backing>(mem: &'t Memory<'t, B>, value: MemPtr<'t>) -> Result<MemPtr<'t, Promise<'t>>> {
+        mem.new_object::<Promise>((value, true))
+    }
+
+    pub fn is_forced(&self) -> bool {
+        self.forced != 0
+    }
+}
+
+impl<'t> Object for Promise<'t> {
+    /// The thunk or value, and whether it is a value
+    type Init = (MemPtr<'t>, bool);
+
+    fn init(hdr: Header, (value, forced): Self::Init) -> Self {
+        Promise { _hdr: hdr, value, forced: forced as u64 }
+    }
+}
+
+impl<'t> MemPtr<'t, Promise<'t>> {
+    /// Returns the memoized result, or nothing if the promise is not forced yet
+    pub fn value(&self) -> Option<MemPtr<'t>> {
+        self.is_forced().then(|| self.value.clone())
+    }
+
+    /// Returns the result of the promise, calling the thunk with `call` if
+    /// it is not forced yet. If the thunk forced the promise itself, the
+    /// result of that inner force is kept, as `force` requires.
+    pub fn force(&self, call: impl FnOnce(MemPtr<'t>) -> Result<MemPtr<'t>>) -> Result<MemPtr<'t>> {
+        if let Some(value) = self.value() {
+            return Ok(value);
+        }
+        let result = call(self.value.clone())?;
+        if let Some(value) = self.value() {
+            return Ok(value);
+        }
+        // the result and the state are set in one update, so the
+        // promise is never seen forced with the thunk as its value
+        let promise = self.cast_mut::<Promise>()?;
+        *promise = Promise { _hdr: promise._hdr, value: result.clone(), forced: 1 };
+        Ok(result)
+    }
+}
+
+/// A mutable box holding a single value, as made by `box`. Variables
+/// that are assigned are kept in boxes once closures are converted,
+/// so that the closures capturing them share the box.
+#[derive(ChunkContent, Trace)]
+#[tag(20)]
+pub struct MBox<'t> {
+    _hdr: Header,
+    value: MemPtr<'t>
+}
+
+impl<'t> MBox<'t> {
+    /// Allocates a box holding the value
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, value: MemPtr<'t>) -> Result<MemPtr<'t, MBox<'t>>> {
+        mem.new_object::<MBox>(value)
+    }
+}
+
+impl<'t> Object for MBox<'t> {
+    type Init = MemPtr<'t>;
+
+    fn init(hdr: Header, value: Self::Init) -> Self {
+        MBox { _hdr: hdr, value }
+    }
+}
+
+impl<'t> MemPtr<'t, MBox<'t>> {
+    /// Returns the value in the box, as `unbox`
+    pub fn unbox(&self) -> MemPtr<'t> {
+        self.value.clone()
+    }
+
+    /// Replaces the value in the box, as `set-box!`, fails if the box is frozen
+    pub fn set(&self, value: MemPtr<'t>) -> Result<()> {
+        self.cast_mut::<MBox>()?.value = value;
+        Ok(())
+    }
+}
+
+/// Returns the integer as a fixnum if it fits,
+/// allocates a number chunk for it otherwise.
+pub fn integer<'t, B: Backing>(mem: &'t Memory<'t, B>, n: i64) -> Result<MemPtr<'t>> {
+    if let Some(fixnum) = <MemPtr>::fixnum(n) {
+        return Ok(fixnum);
+    }
+    let ptr = mem.allocate_raw::<Number>(0)?;
+    ptr.modify::<Number>(|number| number.n = n);
+    Ok(ptr)
+}
+
+/// A floating point number
+#[derive(ChunkContent)]
+#[tag(5)]
+pub struct Flonum {
+    _hdr: Header,
+    pub f: f64
+}
+
+impl Flonum {
+    /// Allocates a flonum holding the given float
+    pub fn new<'t, B: Backing>(mem: &'t Memory<'t, B>, f: f64) -> Result<MemPtr<'t, Flonum>> {
+        let ptr = mem.allocate_raw::<Flonum>(0)?;
+        ptr.modify::<Flonum>(|flonum| flonum.f = f);
+        ptr.downcast()
+    }
+}
+
+/// An exact fraction, in lowest terms and with a denominator larger than one
+#[derive(ChunkContent, Trace)]
+#[tag(7)]
+pub struct Rational<'t> {
+    _hdr: Header,
+    /// Integer with the sign of the fraction
+    pub numerator: MemPtr<'t>,
+    /// Positive integer
+    pub denominator: MemPtr<'t>
+}
+
+impl<'t> Object for Rational<'t> {
+    type Init = (MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (numerator, denominator): Self::Init) -> Self {
+        Rational { _hdr: hdr, numerator, denominator }
+    }
+}
+
+impl MemPtr<'_, Rational<'_>> {
+    /// Returns the numerator and denominator of the fraction
+    pub fn value(&self) -> Result<(BigInt, BigInt)> {
+        let value = |ptr: &MemPtr<'_>| ptr.as_numeric().and_then(|n| n.as_big()).ok_or_else(|| anyhow!("not an integer: {:?}", ptr));
+        Ok((value(&self.numerator)?, value(&self.denominator)?))
+    }
+}
+
+/// Returns the fraction in lowest terms, which is
+/// an integer if the denominator divides the numerator
+pub fn rational<'t, B: Backing>(mem: &'t Memory<'t, B>, numerator: &BigInt, denominator: &BigInt) -> Result<MemPtr<'t>> {
+    match Numeric::ratio(numerator, denominator)? {
+        Numeric::Ratio(n, d) => Ok(mem.new_object::<Rational>((Numeric::Big(n).allocate(mem)?, Numeric::Big(d).allocate(mem)?))?.upcast()),
+        n => n.allocate(mem)
+    }
+}
+
+/// The special values of the language, see `MemPtr::special`
+const TRUE: usize = 1;
+const FALSE: usize = 2;
+const EOF: usize = 3;
+const UNSPECIFIED: usize = 4;
+/// Characters are the special values from here on, 
+/// offset by their Unicode scalar value
+const CHAR: usize = 1 << 24;
+
+/// Returns the character, which is encoded in the pointer itself
+pub fn character<'t>(c: char) -> MemPtr<'t> {
+    <MemPtr>::special(CHAR + c as usize)
+}
+
+impl<'t, B: Backing> Memory<'t, B> {
+    /// The boolean true, `#t`
+    pub fn true_(&self) -> MemPtr<'t> {
+        <MemPtr>::special(TRUE)
+    }
+
+    /// The boolean false, `#f`, the only value that counts as false
+    pub fn false_(&self) -> MemPtr<'t> {
+        <MemPtr>::special(FALSE)
+    }
+
+    /// Returns `#t` or `#f`
+    pub fn boolean(&self, b: bool) -> MemPtr<'t> {
+        if b { self.true_() } else { self.false_() }
+    }
+
+    /// The empty list, `'()`, which is the null pointer
+    pub fn nil(&self) -> MemPtr<'t> {
+        MemPtr::null()
+    }
+
+    /// The value returned by reading past the end of the input
+    pub fn eof(&self) -> MemPtr<'t> {
+        <MemPtr>::special(EOF)
+    }
+
+    /// The value of expressions whose value is not specified, such as assignments
+    pub fn unspecified(&self) -> MemPtr<'t> {
+        <MemPtr>::special(UNSPECIFIED)
+    }
+}
+
+impl<C> MemPtr<'_, C> {
+    /// Returns the boolean if the value is `#t` or `#f`
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.as_special() {
+            Some(TRUE) => Some(true),
+            Some(FALSE) => Some(false),
+            _ => None
+        }
+    }
+
+    /// Returns true if the value is `#f`, every other value counts as true
+    pub fn is_false(&self) -> bool {
+        self.as_special() == Some(FALSE)
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.as_special() == Some(EOF)
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        self.as_special() == Some(UNSPECIFIED)
+    }
+
+    /// Returns the character if the value is one
+    pub fn as_char(&self) -> Option<char> {
+        let c = self.as_special()?.checked_sub(CHAR)?;
+        char::from_u32(u32::try_from(c).ok()?)
+    }
+}
+
+/// Allocates a list of the given values, terminated by the empty list
+pub fn list<'t, B: Backing>(mem: &'t Memory<'t, B>, values: impl IntoIterator<Item = MemPtr<'t>, IntoIter: DoubleEndedIterator>) -> Result<MemPtr<'t>> {
+    values.into_iter().rev().try_fold(MemPtr::null(), |cdr, car| Ok(Pair::new(mem, car, cdr)?.upcast()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a value behind some garbage, so that it moves, collects with
+    /// the value as the only root, and returns where the value moved to
+    fn survives_collection<'t>(mem: &'t Memory<'t>, build: impl FnOnce() -> MemPtr<'t>) -> MemPtr<'t> {
+        mem.allocate_bytes(16).unwrap();
+        let mut root = build();
+        mem.collect(&mut root);
+        root
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let foo = Symbol::new(&mem, "foo").unwrap();
+        assert!(foo.name().unwrap() == "foo");
+        assert!(Symbol::new(&mem, "foo").unwrap() == foo);
+        assert!(Symbol::new(&mem, "bar").unwrap() != foo);
+        assert!(mem.interned(b"foo") == Some(foo.upcast()));
+
+        // interned symbols survive collections, and keep being found when they move
+        mem.allocate_bytes(100).unwrap();
+        let baz = Symbol::new(&mem, "baz").unwrap().upcast();
+        mem.collect(&mut ());
+        assert!(mem.interned(b"baz").unwrap() != baz);
+        let baz = Symbol::new(&mem, "baz").unwrap();
+        assert!(baz.name().unwrap() == "baz" && mem.interned(b"baz") == Some(baz.upcast()));
+        assert!(Symbol::new(&mem, "foo").unwrap().name().unwrap() == "foo");
+    }
+
+    #[test]
+    fn test_fresh_symbols() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let (a, b) = (Symbol::fresh(&mem, "tmp").unwrap(), Symbol::fresh(&mem, "tmp").unwrap());
+        assert!(a != b && a.name().unwrap().starts_with("tmp") && a.name().unwrap() != b.name().unwrap());
+        // never the interned symbol of the same name
+        let name = a.name().unwrap().to_string();
+        assert!(Symbol::new(&mem, &name).unwrap() != a && mem.interned(name.as_bytes()) != Some(a.clone().upcast()));
+        assert!(!a.is_interned() && Symbol::new(&mem, &name).unwrap().is_interned());
+        assert!(crate::printer::print(&a.clone().upcast()) == format!("#{{{}}}", name) && crate::printer::print(&Symbol::new(&mem, &name).unwrap().upcast()) == name);
+
+        // kept alive only while reachable
+        let used = mem.used();
+        let mut root = a.upcast();
+        mem.collect(&mut root);
+        assert!(root.downcast::<Symbol>().unwrap().name().unwrap() == name && mem.used() < used);
+    }
+
+    #[test]
+    fn test_list_iter() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let numbers = (1..=5).map(|n| <MemPtr>::fixnum(n).unwrap()).collect::<Vec<_>>();
+        let proper = list(&mem, numbers.clone()).unwrap();
+        assert!(proper.downcast::<Pair>().unwrap().iter().collect::<Result<Vec<_>>>().unwrap() == numbers);
+        assert!(ListIter::new(MemPtr::null()).next().is_none());
+
+        let dotted = Pair::new(&mem, numbers[0].clone(), numbers[1].clone()).unwrap();
+        let mut iter = dotted.iter();
+        assert!(iter.next().unwrap().unwrap() == numbers[0]);
+        assert!(iter.next().unwrap().is_err() && iter.next().is_none());
+
+        for len in 1..=4 {
+            let circular = Pair::new(&mem, numbers[0].clone(), MemPtr::null()).unwrap();
+            let mut last = circular.clone();
+            for _ in 1..len {
+                last = Pair::new(&mem, numbers[1].clone(), last.upcast()).unwrap();
+            }
+            circular.modify::<Pair>(|pair| pair.cdr = last.clone().upcast());
+            let items = last.iter().collect::<Vec<_>>();
+            assert!(items.len() <= 2 * len + 1 && items.last().unwrap().is_err());
+        }
+    }
+
+    #[test]
+    fn test_unchecked() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let numbers = (1..=3).map(|n| <MemPtr>::fixnum(n).unwrap()).collect::<Vec<_>>();
+        let list = list(&mem, numbers.clone()).unwrap();
+        assert!(list.car().unwrap() == numbers[0] && list.cdr().unwrap().car().unwrap() == numbers[1]);
+        assert!(numbers[0].car().is_err() && MemPtr::null().cdr().is_err());
+        unsafe {
+            assert!(list.car_unchecked() == numbers[0] && list.cdr_unchecked().cdr_unchecked().car_unchecked() == numbers[2]);
+        }
+        let vector = Vector::make(&mem, 2, Some(numbers[2].clone())).unwrap();
+        assert!(unsafe { vector.ref_unchecked(1) } == numbers[2]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "unchecked downcast")]
+    fn test_unchecked_misuse() {
+        let mut data: [u64 ; 10] = [ 0 ; 10 ];
+        let mem = Memory::new(&mut data);
+        let bytes = mem.allocate_bytes(8).unwrap();
+        unsafe { bytes.car_unchecked() };
+    }
+
+    #[test]
+    fn test_keywords() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let foo = Keyword::new(&mem, "foo").unwrap();
+        assert!(foo == Keyword::new(&mem, "foo").unwrap() && foo != Keyword::new(&mem, "bar").unwrap());
+        assert!(foo.name().unwrap() == "foo");
+        // apart from the symbols
+        let symbol = Symbol::new(&mem, "foo").unwrap().upcast();
+        assert!(symbol != foo.clone().upcast() && symbol.downcast::<Symbol>().unwrap().name().unwrap() == "foo");
+        assert!(mem.interned(b"foo") == Some(symbol));
+
+        // interned keywords survive collections
+        let mut root = MemPtr::null();
+        mem.collect(&mut root);
+        assert!(Keyword::new(&mem, "foo").unwrap().name().unwrap() == "foo");
+    }
+
+    #[test]
+    fn test_classify() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        assert!(matches!(MemPtr::null().classify(), Value::Null));
+        assert!(matches!(<MemPtr>::fixnum(-3).unwrap().classify(), Value::Fixnum(-3)));
+        assert!(matches!(mem.true_().classify(), Value::Bool(true)) && matches!(character('x').classify(), Value::Char('x')));
+        assert!(matches!(mem.eof().classify(), Value::Eof) && matches!(mem.unspecified().classify(), Value::Unspecified));
+        let pair = Pair::new(&mem, MemPtr::null(), MemPtr::null()).unwrap();
+        assert!(matches!(pair.clone().upcast().classify(), Value::Pair(p) if p == pair));
+        let len = match Symbol::new(&mem, "x").unwrap().upcast().classify() {
+            Value::Symbol(symbol) => symbol.name().unwrap().len(),
+            _ => 0
+        };
+        assert!(len == 1);
+        assert!(matches!(Flonum::new(&mem, 0.5).unwrap().upcast().classify(), Value::Flonum(x) if x.f == 0.5));
+        assert!(matches!(mem.allocate_bytes(1).unwrap().classify(), Value::Bytes(_)));
+        let ephemeron = mem.allocate_ephemeron(pair.clone().upcast(), pair.upcast()).unwrap();
+        assert!(matches!(ephemeron.classify(), Value::Other(other) if other == ephemeron));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        assert!(i64::try_from(n(-5)).unwrap() == -5 && i64::try_from(integer(&mem, i64::MIN).unwrap()).unwrap() == i64::MIN);
+        assert!(usize::try_from(n(5)).unwrap() == 5);
+        assert!(usize::try_from(n(-5)).unwrap_err().to_string() == "expected a non-negative integer, got -5");
+        let big = crate::numeric::mul(&mem, &integer(&mem, i64::MAX).unwrap(), &n(2)).unwrap();
+        assert!(i64::try_from(big.clone()).is_err() && f64::try_from(big).unwrap() == i64::MAX as f64 * 2.0);
+        assert!(f64::try_from(Flonum::new(&mem, 0.5).unwrap().upcast()).unwrap() == 0.5 && f64::try_from(n(2)).unwrap() == 2.0);
+        assert!(i64::try_from(Flonum::new(&mem, 1.0).unwrap().upcast()).unwrap_err().to_string() == "expected an integer, got 1.0");
+        assert!(!bool::try_from(mem.false_()).unwrap() && bool::try_from(n(0)).is_err());
+        assert!(char::try_from(character('λ')).unwrap() == 'λ' && char::try_from(mem.nil()).is_err());
+        let str = Str::new(&mem, "hello").unwrap().upcast();
+        assert!(String::try_from(str.clone()).unwrap() == "hello");
+        assert!(String::try_from(Symbol::new(&mem, "hello").unwrap().upcast()).unwrap_err().to_string() == "expected a string, got hello");
+
+        // lists and vectors, element by element
+        let numbers = list(&mem, [n(1), n(2), n(3)]).unwrap();
+        assert!(Vec::<i64>::try_from(numbers.clone()).unwrap() == [1, 2, 3] && Vec::<i64>::try_from(MemPtr::null()).unwrap().is_empty());
+        let nested = list(&mem, [numbers, MemPtr::null()]).unwrap();
+        assert!(Vec::<Vec<usize>>::try_from(nested).unwrap() == [vec![1, 2, 3], vec![]]);
+        let vector = Vector::make(&mem, 2, Some(str)).unwrap().upcast();
+        assert!(Vec::<String>::try_from(vector).unwrap() == ["hello", "hello"]);
+        assert!(Vec::<i64>::try_from(list(&mem, [n(1), mem.true_()]).unwrap()).unwrap_err().to_string() == "expected an integer, got #t");
+        assert!(Vec::<i64>::try_from(n(1)).is_err() && Vec::<i64>::try_from(Vector::make(&mem, 1, None).unwrap().upcast()).is_err());
+
+        // the error prints the value, which may be circular
+        let circular = list(&mem, [n(1), n(2)]).unwrap();
+        circular.cdr().unwrap().modify::<Pair>(|pair| pair.cdr = circular.clone());
+        assert!(i64::try_from(circular.clone()).unwrap_err().to_string() == "expected an integer, got (1 2 1 2 . #<cycle>)");
+        assert!(Vec::<i64>::try_from(circular).is_err());
+    }
+
+    #[test]
+    fn test_into_value() {
+        let mut data: [u64 ; 300] = [ 0 ; 300 ];
+        let mem = Memory::new(&mut data);
+        let value = mem.value(("foo", 42, [1, 2, 3])).unwrap();
+        assert!(crate::printer::print(&value) == "(\"foo\" 42 (1 2 3))");
+        assert!(mem.value(()).unwrap().is_null() && crate::diff::eqv(&mem.value(i64::MAX).unwrap(), &integer(&mem, i64::MAX).unwrap()));
+        assert!(mem.value(u64::MAX).unwrap().as_numeric().unwrap().as_big() == Some(BigInt::from_u64(u64::MAX)));
+        assert!(crate::printer::print(&mem.value(u64::MAX).unwrap()) == u64::MAX.to_string() && mem.value(7usize).unwrap().as_fixnum() == Some(7));
+        let value = mem.value((true, 'x', 0.5, None::<i64>, Some(String::from("s")), vec![(1u8, -1i32)])).unwrap();
+        assert!(crate::printer::print(&value) == "(#t #\\x 0.5 #f \"s\" ((1 -1)))");
+        let symbol = Symbol::new(&mem, "sym").unwrap();
+        let values: &[MemPtr] = &[symbol.clone().upcast(), MemPtr::null()];
+        assert!(crate::printer::print(&mem.value((symbol, values)).unwrap()) == "(sym (sym ()))");
+
+        // and back
+        assert!(Vec::<i64>::try_from(mem.value([4, 5]).unwrap()).unwrap() == [4, 5]);
+    }
+
+    #[test]
+    fn test_singletons() {
+        let mut data: [u64 ; 10] = [ 0 ; 10 ];
+        let mem = Memory::new(&mut data);
+        let values = [mem.true_(), mem.false_(), mem.nil(), mem.eof(), mem.unspecified()];
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert!((a == b) == (i == j));
+            }
+        }
+        assert!(mem.true_().as_bool() == Some(true) && mem.false_().as_bool() == Some(false));
+        assert!(mem.boolean(true) == mem.true_() && mem.boolean(false) == mem.false_());
+        assert!(mem.false_().is_false() && !mem.nil().is_false() && !<MemPtr>::fixnum(0).unwrap().is_false());
+        assert!(mem.nil().is_null() && mem.nil().as_bool().is_none());
+        assert!(mem.eof().is_eof() && !mem.eof().is_unspecified());
+        assert!(mem.unspecified().is_unspecified() && !mem.unspecified().is_eof());
+        assert!(mem.used() == 0);
+    }
+
+    #[test]
+    fn test_characters() {
+        for c in ['a', '\0', '\n', 'é', '🦀', char::MAX] {
+            let ptr = character(c);
+            assert!(ptr.as_char() == Some(c) && ptr == character(c));
+            assert!(!ptr.is_eof() && ptr.as_bool().is_none());
+        }
+        assert!(character('a') != character('b'));
+        assert!(<MemPtr>::special(TRUE).as_char().is_none());
+        assert!(<MemPtr>::fixnum('a' as i64).unwrap().as_char().is_none());
+    }
+
+    #[test]
+    fn test_vectors() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let empty = Vector::make(&mem, 0, None).unwrap();
+        assert!(empty.is_empty() && empty.ref_(0).is_err());
+        let vector = Vector::make(&mem, 3, Some(<MemPtr>::fixnum(7).unwrap())).unwrap();
+        assert!(vector.len() == 3 && vector.ref_(2).unwrap().as_fixnum() == Some(7));
+        assert!(vector.set(3, MemPtr::null()).is_err() && vector.ref_(3).is_err());
+        assert!(Vector::make(&mem, 1, None).unwrap().ref_(0).is_err());
+
+        // the slots are traced, and updated when their values move
+        let root = survives_collection(&mem, || {
+            let symbol = Symbol::new(&mem, "foo").unwrap().upcast();
+            let pair = Pair::new(&mem, <MemPtr>::fixnum(1).unwrap(), MemPtr::null()).unwrap().upcast();
+            vector.set(0, pair).unwrap();
+            vector.set(1, symbol).unwrap();
+            vector.upcast()
+        });
+        let vector = root.downcast::<Vector>().unwrap();
+        assert!(vector.ref_(0).unwrap().downcast::<Pair>().unwrap().car.as_fixnum() == Some(1));
+        assert!(vector.ref_(1).unwrap() == Symbol::new(&mem, "foo").unwrap().upcast());
+        assert!(vector.slots().unwrap()[2].as_fixnum() == Some(7));
+    }
+
+    #[test]
+    fn test_bytevectors() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let empty = Bytevector::new(&mem, &[]).unwrap();
+        assert!(empty.is_empty() && empty.bytes().unwrap().is_empty() && empty.get(0).is_err());
+        let filled = Bytevector::make(&mem, 9, 0xff).unwrap();
+        assert!(filled.len() == 9 && filled.bytes().unwrap() == [0xff; 9] && mem.used() == 2 + 4);
+        let bytes = survives_collection(&mem, || Bytevector::new(&mem, b"binary").unwrap().upcast());
+        let bytes = bytes.downcast::<Bytevector>().unwrap();
+        assert!(bytes.get(0).unwrap() == b'b' && bytes.get(6).is_err());
+        bytes.set(5, b'Y').unwrap();
+        assert!(bytes.bytes().unwrap() == b"binarY" && bytes.set(6, 0).is_err());
+    }
+
+    #[test]
+    fn test_hash_tables() {
+        let mut data: [u64 ; 4000] = [ 0 ; 4000 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let eq = HashTable::new(&mem, Comparator::Eq).unwrap();
+        let eqv = HashTable::new(&mem, Comparator::Eqv).unwrap();
+        let equal = HashTable::new(&mem, Comparator::Equal).unwrap();
+        let key = |i: i64| list(&mem, [Str::new(&mem, "key").unwrap().upcast(), integer(&mem, i64::MAX - i).unwrap()]).unwrap();
+
+        // enough entries to grow the buckets a few times
+        let keys = (0..50).map(key).collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            for table in [&eq, &eqv, &equal] {
+                table.insert(&mem, key.clone(), n(i as i64)).unwrap();
+            }
+        }
+        for table in [&eq, &eqv, &equal] {
+            assert!(table.len() == 50 && table.entries().unwrap().len() == 50);
+            assert!(keys.iter().enumerate().all(|(i, key)| table.get(key).unwrap() == Some(n(i as i64))));
+        }
+        assert!(eq.get(&key(3)).unwrap().is_none() && eqv.get(&key(3)).unwrap().is_none());
+        assert!(equal.get(&key(3)).unwrap() == Some(n(3)));
+
+        // numbers are keys by value for eqv, not for eq
+        let big = integer(&mem, i64::MAX).unwrap();
+        eq.insert(&mem, big.clone(), n(-1)).unwrap();
+        eqv.insert(&mem, big, n(-1)).unwrap();
+        assert!(eq.get(&integer(&mem, i64::MAX).unwrap()).unwrap().is_none());
+        assert!(eqv.get(&integer(&mem, i64::MAX).unwrap()).unwrap() == Some(n(-1)));
+        assert!(eqv.get(&Flonum::new(&mem, i64::MAX as f64).unwrap().upcast()).unwrap().is_none());
+
+        // inexact keys are told apart by their bits
+        let float = |f: f64| Flonum::new(&mem, f).unwrap().upcast();
+        eqv.insert(&mem, float(0.0), n(0)).unwrap();
+        eqv.insert(&mem, float(f64::NAN), n(1)).unwrap();
+        eqv.insert(&mem, float(f64::NAN), n(2)).unwrap();
+        assert!(eqv.get(&float(0.0)).unwrap() == Some(n(0)) && eqv.get(&float(-0.0)).unwrap().is_none());
+        assert!(eqv.get(&float(f64::NAN)).unwrap() == Some(n(2)) && eqv.len() == 53);
+        assert!(eqv.remove(&float(0.0)).unwrap().is_some() && eqv.remove(&float(f64::NAN)).unwrap().is_some());
+
+        // removing entries keeps the others reachable, and replacing keeps the count
+        for key in keys.iter().step_by(2) {
+            assert!(equal.remove(key).unwrap().is_some());
+        }
+        assert!(equal.remove(&keys[0]).unwrap().is_none() && equal.len() == 25);
+        assert!(keys.iter().enumerate().all(|(i, key)| equal.get(key).unwrap() == (i % 2 == 1).then(|| n(i as i64))));
+        equal.insert(&mem, key(1), n(100)).unwrap();
+        assert!(equal.len() == 25 && equal.get(&keys[1]).unwrap() == Some(n(100)));
+
+        // keys and values are traced, and keep their hash when they move
+        let mut root = eq.upcast();
+        mem.collect(&mut root);
+        let eq = root.downcast::<HashTable>().unwrap();
+        let entries = eq.entries().unwrap();
+        assert!(entries.len() == 51 && entries.iter().all(|(key, value)| eq.get(key).unwrap() == Some(value.clone())));
+    }
+
+    #[test]
+    fn test_hash_tables_beyond_identity_hashes() {
+        let mut data = vec![0u64 ; 400_000];
+        let mem = Memory::new(&mut data);
+        let table = HashTable::new(&mem, Comparator::Eq).unwrap();
+        // more chunks than there are identity hashes, which have
+        // to be spread over all buckets to keep the probes short
+        let keys = (0..40_000).map(|i| Flonum::new(&mem, i as f64).unwrap().upcast()).collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            table.insert(&mem, key.clone(), <MemPtr>::fixnum(i as i64).unwrap()).unwrap();
+        }
+        assert!(table.len() == 40_000);
+        assert!(keys.iter().enumerate().all(|(i, key)| table.get(key).unwrap() == <MemPtr>::fixnum(i as i64)));
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let body = list(&mem, [Symbol::new(&mem, "x").unwrap().upcast()]).unwrap();
+        let env = Vector::make(&mem, 1, Some(<MemPtr>::fixnum(1).unwrap())).unwrap().upcast();
+        let arity = Arity { required: 1, rest: false };
+        let closure = Closure::new(&mem, body.clone(), env, arity).unwrap();
+        assert!(closure.arity() == arity && closure.code == body);
+        assert!(arity.accepts(1) && !arity.accepts(0) && !arity.accepts(2));
+        let variadic = Arity { required: 1, rest: true };
+        assert!(variadic.accepts(1) && variadic.accepts(3) && !variadic.accepts(0));
+
+        // the code and environment are traced
+        let closure = survives_collection(&mem, || closure.upcast()).downcast::<Closure>().unwrap();
+        assert!(closure.arity() == arity && closure.code.downcast::<Pair>().unwrap().car == Symbol::new(&mem, "x").unwrap().upcast());
+        assert!(closure.env.downcast::<Vector>().unwrap().ref_(0).unwrap().as_fixnum() == Some(1));
+    }
+
+    #[test]
+    fn test_promises() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let thunk = Symbol::new(&mem, "thunk").unwrap().upcast();
+        let promise = Promise::delay(&mem, thunk.clone()).unwrap();
+        assert!(!promise.is_forced() && promise.value().is_none());
+        assert!(promise.force(|_| Err(anyhow!("raised"))).is_err() && !promise.is_forced());
+        assert!(promise.force(|called| { assert!(called == thunk); Ok(n(1)) }).unwrap() == n(1));
+        // the thunk is called at most once
+        assert!(promise.force(|_| panic!("called twice")).unwrap() == n(1) && promise.value() == Some(n(1)));
+        assert!(Promise::ready(&mem, n(2)).unwrap().force(|_| unreachable!()).unwrap() == n(2));
+
+        // a thunk forcing its own promise: the inner result wins
+        let promise = Promise::delay(&mem, thunk.clone()).unwrap();
+        let outer = promise.force(|_| {
+            assert!(promise.force(|_| Ok(n(3))).unwrap() == n(3));
+            Ok(n(4))
+        });
+        assert!(outer.unwrap() == n(3) && promise.value() == Some(n(3)));
+
+        // the thunk and the value are traced
+        let promise = survives_collection(&mem, || Promise::delay(&mem, thunk).unwrap().upcast());
+        let promise = promise.downcast::<Promise>().unwrap();
+        assert!(promise.force(Ok).unwrap() == Symbol::new(&mem, "thunk").unwrap().upcast());
+    }
+
+    #[test]
+    fn test_records() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let [point, x, y] = ["point", "x", "y"].map(|name| Symbol::new(&mem, name).unwrap());
+        let point = RecordType::new(&mem, point, &[x.clone(), y.clone()]).unwrap();
+        assert!(point.field_count().unwrap() == 2 && point.field_index(&y).unwrap() == 1);
+        assert!(point.field_names().unwrap() == [x.clone().upcast(), y.clone().upcast()]);
+        let z = Symbol::new(&mem, "z").unwrap();
+        assert!(point.field_index(&z).unwrap_err().to_string() == "record type point has no field z");
+
+        let record = Record::new(&mem, &point, &[n(1), n(2)]).unwrap();
+        assert!(record.is_a(&point) && record.len() == 2 && record.fields().unwrap() == [n(1), n(2)]);
+        record.set_field(point.field_index(&x).unwrap(), n(10)).unwrap();
+        assert!(record.field(0).unwrap() == n(10) && record.field(2).is_err() && record.set_field(2, n(0)).is_err());
+        assert!(Record::new(&mem, &point, &[n(1)]).unwrap_err().to_string() == "record type point has 2 fields, not 1");
+        let other = RecordType::new(&mem, Symbol::new(&mem, "other").unwrap(), &[]).unwrap();
+        let empty = Record::new(&mem, &other, &[]).unwrap();
+        assert!(empty.is_empty() && !empty.is_a(&point) && !record.is_a(&other));
+
+        // the type and the fields are traced
+        let record = survives_collection(&mem, || {
+            record.set_field(1, Str::new(&mem, "y").unwrap().upcast()).unwrap();
+            record.upcast()
+        });
+        let record = record.downcast::<Record>().unwrap();
+        assert!(record.field(0).unwrap() == n(10) && record.field(1).unwrap().downcast::<Str>().unwrap().as_str().unwrap() == "y");
+        let point = record.record_type.downcast::<RecordType>().unwrap();
+        assert!(point.field_index(&Symbol::new(&mem, "y").unwrap()).unwrap() == 1);
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let input = Port::input_string(&mem, "hello").unwrap();
+        let mut buffer = [0; 4];
+        assert!(input.is_input() && input.is_open() && input.read(&mut buffer).unwrap() == 4 && buffer == *b"hell");
+        assert!(input.read(&mut buffer).unwrap() == 1 && input.read(&mut buffer).unwrap() == 0);
+        assert!(input.write(b"x").unwrap_err().to_string() == "not an output port");
+
+        let output = Port::output_string(&mem).unwrap();
+        output.write(b"foo").unwrap();
+        output.write(b"bar").unwrap();
+        assert!(!output.is_input() && output.contents().unwrap() == b"foobar" && input.contents().is_err());
+        output.close().unwrap();
+        output.close().unwrap();
+        assert!(!output.is_open() && !output.is_input());
+        assert!(output.write(b"baz").unwrap_err().to_string() == "port is closed");
+    }
+
+    /// Counts how many times a stream is dropped
+    struct Counted(alloc::sync::Arc<core::sync::atomic::AtomicUsize>);
+
+    impl Stream for Counted {
+        fn is_input(&self) -> bool {
+            false
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_port_finalizers() {
+        let dropped = alloc::sync::Arc::new(core::sync::atomic::AtomicUsize::new(0));
+        let count = || dropped.load(core::sync::atomic::Ordering::SeqCst);
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let closed = Port::new(&mem, Box::new(Counted(dropped.clone()))).unwrap();
+        closed.close().unwrap();
+        assert!(count() == 1);
+        Port::new(&mem, Box::new(Counted(dropped.clone()))).unwrap();
+        let kept = Port::new(&mem, Box::new(Counted(dropped.clone()))).unwrap();
+
+        // the unreachable port is closed by the collector, the closed one is not closed again
+        let mut root = kept.upcast();
+        mem.collect(&mut root);
+        assert!(count() == 2 && root.downcast::<Port>().unwrap().is_open());
+        mem.collect(&mut root);
+        assert!(count() == 2);
+        mem.destroy();
+        assert!(count() == 3);
+    }
+
+    #[test]
+    fn test_conditions() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let irritants = [n(1), Str::new(&mem, "two").unwrap().upcast()];
+        let condition = Condition::new(&mem, ConditionKind::Error, "bad things:", &irritants).unwrap();
+        assert!(condition.kind() == ConditionKind::Error && condition.message().unwrap() == "bad things:");
+        assert!(condition.describe().unwrap() == "bad things: 1 \"two\"");
+        assert!(ListIter::new(condition.irritants.clone()).map(Result::unwrap).collect::<Vec<_>>() == irritants);
+
+        // from failures of the Rust side
+        let error = irritants[1].downcast::<Pair>().unwrap_err();
+        let condition = Condition::from_error(&mem, ConditionKind::WrongType, &error, &irritants[1..]).unwrap();
+        assert!(condition.kind() == ConditionKind::WrongType && condition.describe().unwrap() == "invalid memory chunk \"two\"");
+        let env = Environment::new(&mem, MemPtr::null()).unwrap();
+        let error = env.lookup(&Symbol::new(&mem, "x").unwrap()).unwrap_err();
+        let condition = Condition::from_error(&mem, ConditionKind::Unbound, &error, &[]).unwrap();
+        assert!(condition.message().unwrap() == "unbound variable: x" && condition.irritants.is_null());
+
+        // the message and irritants are traced
+        let condition = survives_collection(&mem, || {
+            Condition::new(&mem, ConditionKind::Arity, "wrong number of arguments", &irritants).unwrap().upcast()
+        });
+        let condition = condition.downcast::<Condition>().unwrap();
+        assert!(condition.kind() == ConditionKind::Arity && condition.describe().unwrap() == "wrong number of arguments 1 \"two\"");
+    }
+
+    #[test]
+    fn test_boxes() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let cell = MBox::new(&mem, n(1)).unwrap();
+        assert!(cell.unbox() == n(1));
+        cell.set(n(2)).unwrap();
+        assert!(cell.unbox() == n(2));
+
+        // the value is traced, and frozen boxes cannot be set
+        let cell = survives_collection(&mem, || {
+            cell.set(Str::new(&mem, "boxed").unwrap().upcast()).unwrap();
+            cell.upcast()
+        });
+        let cell = cell.downcast::<MBox>().unwrap();
+        assert!(cell.unbox().downcast::<Str>().unwrap().as_str().unwrap() == "boxed");
+        mem.freeze();
+        assert!(cell.set(n(3)).is_err() && cell.unbox() != n(3));
+    }
+
+    #[test]
+    fn test_syntax() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let file = Str::new(&mem, "main.scm").unwrap().upcast();
+        let at = |datum, column| Syntax::new(&mem, datum, file.clone(), 3, column).unwrap().upcast();
+        let [x, y] = ["x", "y"].map(|name| Symbol::new(&mem, name).unwrap().upcast());
+        let syntax = Syntax::new(&mem, list(&mem, [at(x.clone(), 2), at(y.clone(), 4)]).unwrap(), file.clone(), 3, 1).unwrap();
+        assert!(syntax.line() == 3 && syntax.column() == 1 && syntax.location().unwrap() == "main.scm:3:1");
+        let anonymous = Syntax::new(&mem, x.clone(), MemPtr::null(), 1, 5).unwrap();
+        assert!(anonymous.location().unwrap() == "1:5");
+
+        // stripping copies what contains syntax objects, and only that
+        let stripped = strip(&mem, &syntax.clone().upcast()).unwrap();
+        assert!(crate::diff::equal(&stripped, &list(&mem, [x.clone(), y.clone()]).unwrap()));
+        let plain = list(&mem, [x.clone()]).unwrap();
+        assert!(strip(&mem, &plain).unwrap() == plain);
+        let vector = Vector::make(&mem, 2, None).unwrap();
+        vector.set(0, at(x.clone(), 7)).unwrap();
+        let stripped = strip(&mem, &vector.clone().upcast()).unwrap().downcast::<Vector>().unwrap();
+        assert!(stripped.ref_(0).unwrap() == x && stripped.ref_(1).is_err() && vector.ref_(0).unwrap() != x);
+
+        // the datum and source are traced
+        let syntax = survives_collection(&mem, || syntax.upcast());
+        assert!(syntax.downcast::<Syntax>().unwrap().location().unwrap() == "main.scm:3:1");
+    }
+
+    #[test]
+    fn test_environments() {
+        let mut data: [u64 ; 500] = [ 0 ; 500 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let [x, y, z] = ["x", "y", "z"].map(|name| Symbol::new(&mem, name).unwrap());
+        let global = Environment::new(&mem, MemPtr::null()).unwrap();
+        global.define(&mem, x.clone(), n(1)).unwrap();
+        global.define(&mem, y.clone(), n(2)).unwrap();
+        let local = Environment::new(&mem, global.clone().upcast()).unwrap();
+        local.define(&mem, x.clone(), n(10)).unwrap();
+        assert!(local.lookup(&x).unwrap() == n(10) && global.lookup(&x).unwrap() == n(1));
+        assert!(local.lookup(&y).unwrap() == n(2) && local.len() == 1);
+        assert!(local.lookup(&z).unwrap_err().to_string() == "unbound variable: z");
+
+        // assignments go to the innermost frame defining the variable
+        local.set(&y, n(20)).unwrap();
+        local.set(&x, n(30)).unwrap();
+        assert!(global.lookup(&y).unwrap() == n(20) && global.lookup(&x).unwrap() == n(1));
+        assert!(local.set(&z, n(0)).is_err());
+        global.define(&mem, x.clone(), n(100)).unwrap();
+        assert!(global.len() == 2 && global.lookup(&x).unwrap() == n(100));
+
+        // frames grow, and are traced along with their parents
+        let names = (0..10).map(|i| Symbol::new(&mem, &format!("v{}", i)).unwrap()).collect::<Vec<_>>();
+        for (i, name) in names.iter().enumerate() {
+            local.define(&mem, name.clone(), Str::new(&mem, &i.to_string()).unwrap().upcast()).unwrap();
+        }
+        let local = survives_collection(&mem, || local.upcast()).downcast::<Environment>().unwrap();
+        assert!(local.len() == 11 && local.lookup(&Symbol::new(&mem, "y").unwrap()).unwrap() == n(20));
+        let v7 = local.lookup(&Symbol::new(&mem, "v7").unwrap()).unwrap();
+        assert!(v7.downcast::<Str>().unwrap().as_str().unwrap() == "7");
+    }
+
+    #[test]
+    fn test_code() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let name = Symbol::new(&mem, "square").unwrap().upcast();
+        let constant = list(&mem, [Str::new(&mem, "constant").unwrap().upcast()]).unwrap();
+        let big = integer(&mem, i64::MAX).unwrap();
+        let code = Code::new(&mem, name, &[1, 2, 3, 0], &[constant, big]).unwrap();
+        assert!(code.bytecode().unwrap() == [1, 2, 3, 0] && code.constants().unwrap() == 2);
+        assert!(code.constant(2).is_err());
+
+        // the constant pool moves with the collector, not just the code object
+        let closure = survives_collection(&mem, || {
+            Closure::new(&mem, code.upcast(), MemPtr::null(), Arity { required: 1, rest: false }).unwrap().upcast()
+        });
+        let code = closure.downcast::<Closure>().unwrap().code.downcast::<Code>().unwrap();
+        assert!(code.name == Symbol::new(&mem, "square").unwrap().upcast() && code.bytecode().unwrap() == [1, 2, 3, 0]);
+        let constant = code.constant(0).unwrap().downcast::<Pair>().unwrap();
+        assert!(constant.car.downcast::<Str>().unwrap().as_str().unwrap() == "constant");
+        assert!(code.constant(1).unwrap().as_numeric() == Some(Numeric::Integer(i64::MAX)));
+    }
+
+    #[test]
+    fn test_continuations() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let bottom = Continuation::capture(&mem, &[n(1), n(2)], MemPtr::null()).unwrap();
+        let frame = list(&mem, [Symbol::new(&mem, "k").unwrap().upcast(), n(3)]).unwrap();
+        let top = Continuation::capture(&mem, &[frame, n(4)], bottom.clone().upcast()).unwrap();
+        assert!(bottom.segment().unwrap() == [n(1), n(2)] && top.next == bottom.clone().upcast());
+        let empty = Continuation::capture(&mem, &[], top.clone().upcast()).unwrap();
+        assert!(empty.segment().unwrap().is_empty() && empty.stack().unwrap().len() == 4);
+
+        // the saved frames are traced, through every segment
+        let continuation = survives_collection(&mem, || empty.upcast());
+        let stack = continuation.downcast::<Continuation>().unwrap().stack().unwrap();
+        assert!(stack.len() == 4 && stack[..2] == [n(1), n(2)] && stack[3] == n(4));
+        let frame = stack[2].downcast::<Pair>().unwrap();
+        assert!(frame.car == Symbol::new(&mem, "k").unwrap().upcast());
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let empty = Str::new(&mem, "").unwrap();
+        assert!(empty.is_empty() && empty.as_str().unwrap() == "" && mem.used() == 2);
+        let s = survives_collection(&mem, || Str::new(&mem, "héllo, wörld").unwrap().upcast());
+        let s = s.downcast::<Str>().unwrap();
+        assert!(s.len() == 14 && s.as_str().unwrap() == "héllo, wörld");
+        assert!(mem.used() == 2 + 2);
+    }
+}
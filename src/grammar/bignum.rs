@@ -0,0 +1,233 @@
+use core::{cmp::Ordering, fmt};
+
+use alloc::vec::Vec;
+
+use anyhow::Result;
+
+use crate::memory::{Backing, ChunkContent, Header, MemPtr, Memory};
+
+/// An integer of any size, as a sign and a magnitude
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    /// Limbs of the magnitude, least significant first,
+    /// without leading zeros (so zero has none)
+    magnitude: Vec<u64>
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> BigInt {
+        BigInt::new(n < 0, [n.unsigned_abs()].into())
+    }
+}
+
+impl BigInt {
+    /// Same as `BigInt::from`, for integers that do not fit in an `i64`
+    pub fn from_u64(n: u64) -> BigInt {
+        BigInt::new(false, [n].into())
+    }
+
+    fn new(negative: bool, mut magnitude: Vec<u64>) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt { negative: negative && !magnitude.is_empty(), magnitude }
+    }
+
+    /// Returns the integer if it fits in an `i64`
+    pub fn to_i64(&self) -> Option<i64> {
+        match self.magnitude[..] {
+            [] => Some(0),
+            [limb] if self.negative => 0i64.checked_sub_unsigned(limb),
+            [limb] => i64::try_from(limb).ok(),
+            _ => None
+        }
+    }
+
+    /// Returns the nearest float
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.magnitude.iter().rev().fold(0.0, |acc, &limb| acc * (1u128 << 64) as f64 + limb as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add(&self.magnitude, &other.magnitude));
+        }
+        // the sign of the result is that of the larger magnitude
+        match compare(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(other.negative, sub(&other.magnitude, &self.magnitude)),
+            _ => BigInt::new(self.negative, sub(&self.magnitude, &other.magnitude))
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut product = alloc::vec![0u64; self.magnitude.len() + other.magnitude.len()];
+        for (i, &a) in self.magnitude.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, &b) in other.magnitude.iter().enumerate() {
+                let limb = product[i + j] as u128 + a as u128 * b as u128 + carry;
+                product[i + j] = limb as u64;
+                carry = limb >> 64;
+            }
+            product[i + other.magnitude.len()] = carry as u64;
+        }
+        BigInt::new(self.negative != other.negative, product)
+    }
+
+    pub fn neg(&self) -> BigInt {
+        BigInt::new(!self.negative, self.magnitude.clone())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Divides, rounding the quotient towards zero, so the remainder has the
+    /// sign of `self`. Returns nothing if `other` is zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        // long division, one bit at a time
+        let mut quotient = alloc::vec![0u64; self.magnitude.len()];
+        let mut remainder = Vec::new();
+        for bit in (0..self.magnitude.len() * 64).rev() {
+            remainder = add(&remainder, &remainder);
+            remainder[0] |= self.magnitude[bit / 64] >> (bit % 64) & 1;
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+            if compare(&remainder, &other.magnitude) != Ordering::Less {
+                remainder = BigInt::new(false, sub(&remainder, &other.magnitude)).magnitude;
+                quotient[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        Some((BigInt::new(self.negative != other.negative, quotient), BigInt::new(self.negative, remainder)))
+    }
+
+    /// Returns the greatest common divisor, which is never negative
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (BigInt::new(false, self.magnitude.clone()), BigInt::new(false, other.magnitude.clone()));
+        while let Some((_, remainder)) = a.div_rem(&b) {
+            (a, b) = (b, remainder);
+        }
+        a
+    }
+}
+
+/// Adds two magnitudes
+fn add(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = false;
+    for (i, &limb) in long.iter().enumerate() {
+        let (limb, c1) = limb.overflowing_add(short.get(i).copied().unwrap_or(0));
+        let (limb, c2) = limb.overflowing_add(carry as u64);
+        sum.push(limb);
+        carry = c1 || c2;
+    }
+    sum.push(carry as u64);
+    sum
+}
+
+/// Subtracts the magnitude `b` from the magnitude `a`, which is at least as large
+fn sub(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = false;
+    for (i, &limb) in a.iter().enumerate() {
+        let (limb, b1) = limb.overflowing_sub(b.get(i).copied().unwrap_or(0));
+        let (limb, b2) = limb.overflowing_sub(borrow as u64);
+        difference.push(limb);
+        borrow = b1 || b2;
+    }
+    difference
+}
+
+/// Compares two magnitudes without leading zeros
+fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare(&self.magnitude, &other.magnitude),
+            (true, true) => compare(&other.magnitude, &self.magnitude)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Largest power of ten that fits in a limb
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        // split the magnitude in chunks of 19 decimal digits, least significant first
+        let mut chunks = Vec::new();
+        let mut rest = self.magnitude.clone();
+        while !rest.is_empty() {
+            let mut remainder = 0u128;
+            for limb in rest.iter_mut().rev() {
+                let value = remainder << 64 | *limb as u128;
+                *limb = (value / CHUNK as u128) as u64;
+                remainder = value % CHUNK as u128;
+            }
+            chunks.push(remainder as u64);
+            while rest.last() == Some(&0) {
+                rest.pop();
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                rest.iter().rev().try_for_each(|chunk| write!(f, "{:019}", chunk))
+            }
+        }
+    }
+}
+
+/// An integer too large to be a `Number`, with the limbs
+/// of its magnitude in the cells following its sign
+#[derive(ChunkContent)]
+#[tag(6)]
+pub struct Bignum {
+    _hdr: Header,
+    negative: u64
+}
+
+impl Bignum {
+    /// Allocates a bignum holding the given integer
+    pub fn new<'t, B: Backing>(mem: &'t Memory<'t, B>, n: &BigInt) -> Result<MemPtr<'t, Bignum>> {
+        let ptr = mem.allocate_raw::<Bignum>(isize::try_from(n.magnitude.len())?)?;
+        ptr.modify::<Bignum>(|bignum| bignum.negative = n.negative as u64);
+        ptr.tail_slice_mut::<Bignum>()?.copy_from_slice(&n.magnitude);
+        ptr.downcast()
+    }
+}
+
+impl MemPtr<'_, Bignum> {
+    /// Returns the integer the bignum holds
+    pub fn value(&self) -> Result<BigInt> {
+        Ok(BigInt::new(self.negative != 0, self.tail_slice::<Bignum>()?.to_vec()))
+    }
+}
@@ -0,0 +1,101 @@
+use alloc::string::{String, ToString};
+
+use anyhow::Result;
+
+use crate::{memory::{Backing, ChunkContent, Header, MemPtr, Memory, Object, Trace}, printer::Printer};
+
+use super::{list, ListIter, Str};
+
+/// What went wrong, so handlers can tell conditions apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionKind {
+    /// Raised by the program, as by `error`
+    Error,
+    /// A value of the wrong type, such as a bad `cast`
+    WrongType,
+    /// A procedure called with the wrong number of arguments
+    Arity,
+    /// An index out of range
+    Range,
+    /// A variable that was never defined
+    Unbound,
+    /// Reading or writing a port failed
+    Io
+}
+
+impl ConditionKind {
+    const ALL: [ConditionKind; 6] = [
+        ConditionKind::Error, ConditionKind::WrongType, ConditionKind::Arity,
+        ConditionKind::Range, ConditionKind::Unbound, ConditionKind::Io
+    ];
+
+    /// The name printed for the kind
+    pub fn name(self) -> &'static str {
+        match self {
+            ConditionKind::Error => "error",
+            ConditionKind::WrongType => "wrong-type",
+            ConditionKind::Arity => "arity",
+            ConditionKind::Range => "range",
+            ConditionKind::Unbound => "unbound",
+            ConditionKind::Io => "io"
+        }
+    }
+}
+
+/// An error as a value: what kind of error it is, a message,
+/// and the values it is about, its irritants
+#[derive(ChunkContent, Trace)]
+#[tag(19)]
+pub struct Condition<'t> {
+    _hdr: Header,
+    /// `Str` of the message
+    pub message: MemPtr<'t>,
+    /// List of the irritants
+    pub irritants: MemPtr<'t>,
+    /// The `ConditionKind`, by index
+    kind: u64
+}
+
+impl<'t> Condition<'t> {
+    /// Allocates a condition with the given message and irritants
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, kind: ConditionKind, message: &str, irritants: &[MemPtr<'t>]) -> Result<MemPtr<'t, Condition<'t>>> {
+        let message = Str::new(mem, message)?.upcast();
+        let irritants = list(mem, irritants.iter().cloned())?;
+        mem.new_object::<Condition>((kind, message, irritants))
+    }
+
+    /// Allocates a condition for an error of the Rust side, such as a failed
+    /// `downcast`, with the error and its causes as the message
+    pub fn from_error<B: Backing>(mem: &'t Memory<'t, B>, kind: ConditionKind, error: &anyhow::Error, irritants: &[MemPtr<'t>]) -> Result<MemPtr<'t, Condition<'t>>> {
+        Condition::new(mem, kind, &alloc::format!("{:#}", error), irritants)
+    }
+
+    pub fn kind(&self) -> ConditionKind {
+        ConditionKind::ALL[self.kind as usize]
+    }
+}
+
+impl<'t> Object for Condition<'t> {
+    /// The kind, the `Str` of the message and the list of irritants
+    type Init = (ConditionKind, MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (kind, message, irritants): Self::Init) -> Self {
+        Condition { _hdr: hdr, message, irritants, kind: kind as u64 }
+    }
+}
+
+impl<'t> MemPtr<'t, Condition<'t>> {
+    pub fn message(&self) -> Result<String> {
+        Ok(self.message.downcast::<Str>()?.as_str()?.to_string())
+    }
+
+    /// Describes the condition as its message followed by its irritants,
+    /// as `error` reports it
+    pub fn describe(&self) -> Result<String> {
+        let mut description = self.message()?;
+        for irritant in ListIter::new(self.irritants.clone()) {
+            description += &alloc::format!(" {}", Printer(&irritant?));
+        }
+        Ok(description)
+    }
+}
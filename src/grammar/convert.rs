@@ -0,0 +1,211 @@
+use alloc::{string::{String, ToString}, vec::Vec};
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::{memory::{Backing, MemPtr, Memory}, numeric::Numeric, printer::Printer};
+
+use super::{character, integer, list, BigInt, Flonum, ListIter, Pair, Str, Vector};
+
+/// Fails with the expected type and the value that was found instead
+fn expected(what: &str, ptr: &MemPtr<'_>) -> Error {
+    anyhow!("expected {}, got {}", what, Printer(ptr))
+}
+
+impl<'t> TryFrom<MemPtr<'t>> for i64 {
+    type Error = Error;
+
+    /// Converts an exact integer that fits
+    fn try_from(ptr: MemPtr<'t>) -> Result<i64> {
+        match ptr.as_numeric() {
+            Some(Numeric::Integer(n)) => Ok(n),
+            Some(Numeric::Big(n)) => n.to_i64().ok_or_else(|| expected("a 64-bit integer", &ptr)),
+            _ => Err(expected("an integer", &ptr))
+        }
+    }
+}
+
+impl<'t> TryFrom<MemPtr<'t>> for usize {
+    type Error = Error;
+
+    /// Converts an exact integer that is not negative, such as an index
+    fn try_from(ptr: MemPtr<'t>) -> Result<usize> {
+        let n = i64::try_from(ptr.clone())?;
+        usize::try_from(n).map_err(|_| expected("a non-negative integer", &ptr))
+    }
+}
+
+impl<'t> TryFrom<MemPtr<'t>> for f64 {
+    type Error = Error;
+
+    /// Converts any real number, exact numbers may be rounded
+    fn try_from(ptr: MemPtr<'t>) -> Result<f64> {
+        ptr.as_numeric().map(|n| n.as_f64()).ok_or_else(|| expected("a number", &ptr))
+    }
+}
+
+impl<'t> TryFrom<MemPtr<'t>> for bool {
+    type Error = Error;
+
+    /// Converts `#t` and `#f` only, not the truthiness of other values
+    fn try_from(ptr: MemPtr<'t>) -> Result<bool> {
+        ptr.as_bool().ok_or_else(|| expected("a boolean", &ptr))
+    }
+}
+
+impl<'t> TryFrom<MemPtr<'t>> for char {
+    type Error = Error;
+
+    fn try_from(ptr: MemPtr<'t>) -> Result<char> {
+        ptr.as_char().ok_or_else(|| expected("a character", &ptr))
+    }
+}
+
+impl<'t> TryFrom<MemPtr<'t>> for String {
+    type Error = Error;
+
+    /// Copies the characters of a string
+    fn try_from(ptr: MemPtr<'t>) -> Result<String> {
+        let str = ptr.downcast::<Str>().map_err(|_| expected("a string", &ptr))?;
+        Ok(str.as_str()?.to_string())
+    }
+}
+
+impl<'t, T: TryFrom<MemPtr<'t>, Error = Error>> TryFrom<MemPtr<'t>> for Vec<T> {
+    type Error = Error;
+
+    /// Converts every element of a proper list or a vector, fails
+    /// if any of them does not convert or a slot is empty
+    fn try_from(ptr: MemPtr<'t>) -> Result<Vec<T>> {
+        if let Ok(vector) = ptr.downcast::<Vector>() {
+            return (0..vector.len()).map(|i| T::try_from(vector.ref_(i)?)).collect();
+        }
+        if !ptr.is_null() && ptr.downcast::<Pair>().is_err() {
+            return Err(expected("a list or a vector", &ptr));
+        }
+        ListIter::new(ptr).map(|element| T::try_from(element?)).collect()
+    }
+}
+
+/// Rust data that can be stored as a value, see `Memory::value`
+pub trait IntoValue<'t> {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>>;
+}
+
+impl<'t, B: Backing> Memory<'t, B> {
+    /// Stores the data as a value: numbers as numbers, strings as strings,
+    /// slices, arrays, vectors and tuples as lists, and nothing as `#f`, so
+    /// that `mem.value(("foo", 42, [1, 2, 3]))` is `("foo" 42 (1 2 3))`
+    pub fn value(&'t self, value: impl IntoValue<'t>) -> Result<MemPtr<'t>> {
+        value.into_value(self)
+    }
+}
+
+impl<'t, C> IntoValue<'t> for MemPtr<'t, C> {
+    fn into_value<B: Backing>(self, _mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        Ok(self.upcast())
+    }
+}
+
+macro_rules! into_integer {
+    ($($ty:ty)*) => {
+        $(impl<'t> IntoValue<'t> for $ty {
+            fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+                integer(mem, i64::try_from(self)?)
+            }
+        })*
+    };
+}
+
+into_integer!(i8 i16 i32 i64 u8 u16 u32 isize);
+
+/// Unsigned integers that may not fit in an `i64`, which are stored as a `Bignum` then
+macro_rules! into_unsigned {
+    ($($ty:ty)*) => {
+        $(impl<'t> IntoValue<'t> for $ty {
+            fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+                Numeric::Big(BigInt::from_u64(u64::try_from(self)?)).allocate(mem)
+            }
+        })*
+    };
+}
+
+into_unsigned!(u64 usize);
+
+impl<'t> IntoValue<'t> for f64 {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        Ok(Flonum::new(mem, self)?.upcast())
+    }
+}
+
+impl<'t> IntoValue<'t> for bool {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        Ok(if self { mem.true_() } else { mem.false_() })
+    }
+}
+
+impl<'t> IntoValue<'t> for char {
+    fn into_value<B: Backing>(self, _mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        Ok(character(self))
+    }
+}
+
+impl<'t> IntoValue<'t> for &str {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        Ok(Str::new(mem, self)?.upcast())
+    }
+}
+
+impl<'t> IntoValue<'t> for String {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        self.as_str().into_value(mem)
+    }
+}
+
+impl<'t, T: IntoValue<'t>> IntoValue<'t> for Option<T> {
+    /// Stores the value, or `#f` if there is none
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        match self {
+            Some(value) => value.into_value(mem),
+            None => Ok(mem.false_())
+        }
+    }
+}
+
+impl<'t, T: IntoValue<'t>> IntoValue<'t> for Vec<T> {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        let values = self.into_iter().map(|value| value.into_value(mem)).collect::<Result<Vec<_>>>()?;
+        list(mem, values)
+    }
+}
+
+impl<'t, T: IntoValue<'t>, const N: usize> IntoValue<'t> for [T; N] {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        Vec::from(self).into_value(mem)
+    }
+}
+
+impl<'t, T: IntoValue<'t> + Clone> IntoValue<'t> for &[T] {
+    fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        self.to_vec().into_value(mem)
+    }
+}
+
+macro_rules! into_tuple {
+    ($($name:ident)*) => {
+        impl<'t, $($name: IntoValue<'t>),*> IntoValue<'t> for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn into_value<B: Backing>(self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+                let ($($name,)*) = self;
+                list(mem, [$($name.into_value(mem)?),*])
+            }
+        }
+    };
+}
+
+into_tuple!();
+into_tuple!(T0);
+into_tuple!(T0 T1);
+into_tuple!(T0 T1 T2);
+into_tuple!(T0 T1 T2 T3);
+into_tuple!(T0 T1 T2 T3 T4);
+into_tuple!(T0 T1 T2 T3 T4 T5);
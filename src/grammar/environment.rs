@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+
+use crate::memory::{Backing, ChunkContent, Header, MemPtr, Memory, Object, Trace};
+
+use super::{Symbol, Vector};
+
+/// Number of variables a frame has room for before it grows
+const INITIAL_CAPACITY: usize = 4;
+
+/// A frame of variables, such as the parameters of a procedure call,
+/// whose variables shadow the ones of the frames enclosing it
+#[derive(ChunkContent, Trace)]
+#[tag(12)]
+pub struct Environment<'t> {
+    _hdr: Header,
+    /// The enclosing `Environment`, or the empty list for the outermost one
+    pub parent: MemPtr<'t>,
+    /// `Vector` of the names and values of the variables, in pairs of slots
+    /// in the order they were defined, followed by free slots
+    slots: MemPtr<'t>,
+    /// Number of variables
+    len: u64
+}
+
+impl<'t> Environment<'t> {
+    /// Allocates a frame without variables, enclosed by the given one
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, parent: MemPtr<'t>) -> Result<MemPtr<'t, Environment<'t>>> {
+        let slots = Vector::make(mem, 2 * INITIAL_CAPACITY, None)?.upcast();
+        mem.new_object::<Environment>((parent, slots))
+    }
+
+    /// Number of variables of the frame, not counting the enclosing ones
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if the frame has no variables
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<'t> Object for Environment<'t> {
+    /// The parent and the `Vector` of slots
+    type Init = (MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (parent, slots): Self::Init) -> Self {
+        Environment { _hdr: hdr, parent, slots, len: 0 }
+    }
+}
+
+impl<'t> MemPtr<'t, Environment<'t>> {
+    fn slots(&self) -> Result<MemPtr<'t, Vector>> {
+        self.slots.downcast::<Vector>()
+    }
+
+    /// Returns the index of the variable in this frame, if it is defined here
+    fn index(&self, name: &MemPtr<'_, Symbol<'_>>) -> Result<Option<usize>> {
+        let slots = self.slots()?;
+        Ok(slots.slots()?[..2 * self.len()].chunks(2).position(|variable| variable[0].get() == Some(&name.clone().upcast())))
+    }
+
+    /// Returns the frame defining the variable, searching outwards from this one
+    fn frame(&self, name: &MemPtr<'_, Symbol<'_>>) -> Result<(MemPtr<'t, Environment<'t>>, usize)> {
+        let mut env = self.clone();
+        loop {
+            if let Some(index) = env.index(name)? {
+                return Ok((env, index));
+            }
+            if env.parent.is_null() {
+                return Err(anyhow!("unbound variable: {}", name.name()?));
+            }
+            env = env.parent.downcast::<Environment>()?;
+        }
+    }
+
+    /// Defines the variable in this frame, or assigns it if it is already
+    /// defined here. Variables of the enclosing frames are shadowed.
+    pub fn define<B: Backing>(&self, mem: &'t Memory<'t, B>, name: MemPtr<'t, Symbol<'t>>, value: MemPtr<'t>) -> Result<()> {
+        if let Some(index) = self.index(&name)? {
+            return self.slots()?.set(2 * index + 1, value);
+        }
+        let mut slots = self.slots()?;
+        if 2 * self.len() == slots.len() {
+            let grown = Vector::make(mem, 2 * slots.len(), None)?;
+            grown.slots_mut()?[..slots.len()].clone_from_slice(slots.slots()?);
+            self.cast_mut::<Environment>()?.slots = grown.clone().upcast();
+            slots = grown;
+        }
+        slots.set(2 * self.len(), name.upcast())?;
+        slots.set(2 * self.len() + 1, value)?;
+        self.cast_mut::<Environment>()?.len += 1;
+        Ok(())
+    }
+
+    /// Returns the value of the variable in the innermost frame defining it
+    pub fn lookup(&self, name: &MemPtr<'_, Symbol<'_>>) -> Result<MemPtr<'t>> {
+        let (env, index) = self.frame(name)?;
+        // SAFETY: the slot was set when the variable was defined
+        Ok(unsafe { env.slots()?.ref_unchecked(2 * index + 1) })
+    }
+
+    /// Assigns the variable in the innermost frame defining it,
+    /// fails if no frame does
+    pub fn set(&self, name: &MemPtr<'_, Symbol<'_>>, value: MemPtr<'t>) -> Result<()> {
+        let (env, index) = self.frame(name)?;
+        env.slots()?.set(2 * index + 1, value)
+    }
+}
@@ -0,0 +1,246 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::vec::Vec;
+
+use anyhow::Result;
+
+use crate::{diff::{equal, eqv}, memory::{Backing, Bytes, ChunkContent, Header, MemPtr, Memory, Object, Trace}, numeric::Numeric};
+
+use super::{Bytevector, Pair, Str, Vector};
+
+/// How the keys of a hash table are compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparator {
+    /// By identity, as `eq?`
+    Eq,
+    /// By identity, and numbers by value, as `eqv?`
+    Eqv,
+    /// By structure, see `diff::equal`
+    Equal
+}
+
+impl Comparator {
+    fn matches(self, a: &MemPtr<'_>, b: &MemPtr<'_>) -> bool {
+        match self {
+            Comparator::Eq => a == b,
+            Comparator::Eqv => eqv(a, b),
+            Comparator::Equal => equal(a, b)
+        }
+    }
+
+    /// Returns a hash of the key, the same for all keys that match it
+    fn hash(self, key: &MemPtr<'_>) -> u64 {
+        match self {
+            Comparator::Eq => key.identity_hash(),
+            Comparator::Eqv => hash_eqv(key),
+            Comparator::Equal => hash_equal(key, &mut 16)
+        }
+    }
+}
+
+/// Initial number of buckets, always a power of two
+const INITIAL_CAPACITY: usize = 8;
+
+/// A hash table from keys to values, compared by its `Comparator`.
+///
+/// The buckets are the key/value pairs of slots of a `Vector`, empty
+/// slots are free buckets. Collisions are resolved by linear probing,
+/// and the vector is replaced by one twice as large once it is 3/4 full.
+///
+/// The hashes of the keys are mixed with a seed of the table, so that
+/// the few bits of identity hashes spread over all buckets, and entries
+/// copied from one table to another do not cluster.
+#[derive(ChunkContent, Trace)]
+#[tag(10)]
+pub struct HashTable<'t> {
+    _hdr: Header,
+    buckets: MemPtr<'t>,
+    /// Number of entries
+    len: u64,
+    /// The `Comparator`, by index
+    comparator: u64,
+    seed: u64
+}
+
+/// Seed of the next table, see `HashTable`
+static SEED: AtomicU64 = AtomicU64::new(0);
+
+impl<'t> HashTable<'t> {
+    /// Allocates an empty hash table comparing its keys with the given comparator
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, comparator: Comparator) -> Result<MemPtr<'t, HashTable<'t>>> {
+        let buckets = Vector::make(mem, 2 * INITIAL_CAPACITY, None)?.upcast();
+        let seed = scramble(SEED.fetch_add(1, Ordering::Relaxed));
+        mem.new_object::<HashTable>((buckets, comparator, seed))
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn comparator(&self) -> Comparator {
+        [Comparator::Eq, Comparator::Eqv, Comparator::Equal][self.comparator as usize]
+    }
+}
+
+impl<'t> Object for HashTable<'t> {
+    /// The `Vector` of buckets, the comparator and the seed
+    type Init = (MemPtr<'t>, Comparator, u64);
+
+    fn init(hdr: Header, (buckets, comparator, seed): Self::Init) -> Self {
+        HashTable { _hdr: hdr, buckets, len: 0, comparator: comparator as u64, seed }
+    }
+}
+
+impl<'t> MemPtr<'t, HashTable<'t>> {
+    fn buckets(&self) -> Result<MemPtr<'t, Vector>> {
+        self.buckets.downcast::<Vector>()
+    }
+
+    /// Returns the bucket the key belongs in, if it is free
+    fn home(&self, key: &MemPtr<'_>, capacity: usize) -> usize {
+        scramble(self.comparator().hash(key) ^ self.seed) as usize & (capacity - 1)
+    }
+
+    /// Returns the index of the bucket holding the key, or else of
+    /// the free bucket where it would be inserted
+    fn find(&self, buckets: &MemPtr<'t, Vector>, key: &MemPtr<'_>) -> Result<usize> {
+        let slots = buckets.slots()?;
+        let capacity = slots.len() / 2;
+        let mut index = self.home(key, capacity);
+        loop {
+            match slots[2 * index].get() {
+                Some(other) if !self.comparator().matches(other, key) => index = (index + 1) & (capacity - 1),
+                _ => return Ok(index)
+            }
+        }
+    }
+
+    /// Returns the value of the entry for the key, if any
+    pub fn get(&self, key: &MemPtr<'_>) -> Result<Option<MemPtr<'t>>> {
+        let buckets = self.buckets()?;
+        let index = self.find(&buckets, key)?;
+        Ok(buckets.slots()?[2 * index + 1].get().cloned())
+    }
+
+    /// Sets the value of the entry for the key, adding an entry if there
+    /// is none, and growing the buckets if they are getting full
+    pub fn insert<B: Backing>(&self, mem: &'t Memory<'t, B>, key: MemPtr<'t>, value: MemPtr<'t>) -> Result<()> {
+        let buckets = self.buckets()?;
+        let index = self.find(&buckets, &key)?;
+        let slots = buckets.slots_mut()?;
+        if !slots[2 * index].is_empty() {
+            slots[2 * index + 1].set(value);
+            return Ok(());
+        }
+        if 4 * (self.len() + 1) > 3 * (slots.len() / 2) {
+            self.grow(mem)?;
+            return self.insert(mem, key, value);
+        }
+        slots[2 * index].set(key);
+        slots[2 * index + 1].set(value);
+        self.cast_mut::<HashTable>()?.len += 1;
+        Ok(())
+    }
+
+    /// Moves the entries to twice as many buckets
+    fn grow<B: Backing>(&self, mem: &'t Memory<'t, B>) -> Result<()> {
+        let old = self.buckets()?;
+        let buckets = Vector::make(mem, 2 * old.len(), None)?;
+        for entry in old.slots()?.chunks(2) {
+            if let Some(key) = entry[0].get() {
+                let index = self.find(&buckets, key)?;
+                buckets.slots_mut()?[2 * index..2 * index + 2].clone_from_slice(entry);
+            }
+        }
+        self.cast_mut::<HashTable>()?.buckets = buckets.upcast();
+        Ok(())
+    }
+
+    /// Removes the entry for the key and returns its value, if any
+    pub fn remove(&self, key: &MemPtr<'_>) -> Result<Option<MemPtr<'t>>> {
+        let buckets = self.buckets()?;
+        let mut index = self.find(&buckets, key)?;
+        let slots = buckets.slots_mut()?;
+        if slots[2 * index].take().is_none() {
+            return Ok(None);
+        }
+        let value = slots[2 * index + 1].take();
+        self.cast_mut::<HashTable>()?.len -= 1;
+        // shifts the entries after it back, so that no entry is
+        // separated from its home bucket by a free one
+        let capacity = slots.len() / 2;
+        let mut next = index;
+        loop {
+            next = (next + 1) & (capacity - 1);
+            let Some(other) = slots[2 * next].get() else { break };
+            let home = self.home(other, capacity);
+            // the entry may move to the free bucket if its home
+            // is not cyclically in between the free bucket and itself
+            if (next.wrapping_sub(home) & (capacity - 1)) >= (next.wrapping_sub(index) & (capacity - 1)) {
+                slots.swap(2 * index, 2 * next);
+                slots.swap(2 * index + 1, 2 * next + 1);
+                index = next;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Returns the keys and values of all entries, in no particular order
+    pub fn entries(&self) -> Result<Vec<(MemPtr<'t>, MemPtr<'t>)>> {
+        let buckets = self.buckets()?;
+        Ok(buckets.slots()?.chunks(2)
+            .filter_map(|entry| Some((entry[0].get()?.clone(), entry[1].get()?.clone())))
+            .collect())
+    }
+}
+
+/// Spreads the bits of `x` over the whole word
+fn scramble(x: u64) -> u64 {
+    x.wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(32)
+}
+
+/// Hashes numbers by value, other values by identity
+fn hash_eqv(key: &MemPtr<'_>) -> u64 {
+    match key.as_numeric() {
+        Some(Numeric::Integer(n)) => scramble(n as u64),
+        // values that are only equal if their floats are
+        Some(n @ (Numeric::Big(_) | Numeric::Ratio(..) | Numeric::Float(_))) => scramble(n.as_f64().to_bits()),
+        None => key.identity_hash()
+    }
+}
+
+/// Hashes the contents of strings, bytes and at most `budget`
+/// components of pairs and vectors, other values as `hash_eqv`
+fn hash_equal(key: &MemPtr<'_>, budget: &mut usize) -> u64 {
+    if *budget == 0 {
+        return 0;
+    }
+    *budget -= 1;
+    let bytes = |bytes: &[u8]| bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| (hash ^ *byte as u64).wrapping_mul(0x100_0000_01b3));
+    if let Ok(pair) = key.downcast::<Pair>() {
+        let car = hash_equal(&pair.car, budget);
+        return scramble(car ^ hash_equal(&pair.cdr, budget).rotate_left(1));
+    }
+    if let Ok(vector) = key.downcast::<Vector>() {
+        let slots = vector.slots().unwrap_or_default();
+        return slots.iter().fold(scramble(slots.len() as u64), |hash, slot| {
+            scramble(hash ^ slot.get().map_or(0, |value| hash_equal(value, budget)))
+        });
+    }
+    if let Ok(str) = key.downcast::<Str>() {
+        return bytes(str.as_str().unwrap_or_default().as_bytes());
+    }
+    if let Ok(bytevector) = key.downcast::<Bytevector>() {
+        return bytes(bytevector.bytes().unwrap_or_default());
+    }
+    if key.downcast::<Bytes>().is_ok() {
+        return bytes(key.as_bytes().unwrap_or_default());
+    }
+    hash_eqv(key)
+}
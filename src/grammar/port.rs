@@ -0,0 +1,224 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use anyhow::{anyhow, Result};
+
+use crate::memory::{Backing, ChunkContent, Finalize, Header, MemPtr, Memory};
+
+/// The stream a `Port` reads from or writes to
+pub trait Stream: Send {
+    /// Returns true if the stream is read from, false if it is written to
+    fn is_input(&self) -> bool;
+
+    /// Reads into the buffer, returns the number of bytes read,
+    /// which is zero at the end of the stream
+    fn read(&mut self, _buffer: &mut [u8]) -> Result<usize> {
+        Err(anyhow!("not an input port"))
+    }
+
+    fn write(&mut self, _bytes: &[u8]) -> Result<()> {
+        Err(anyhow!("not an output port"))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the bytes written so far to an in-memory stream
+    fn contents(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+/// Reads from a string
+struct StringInput {
+    bytes: Vec<u8>,
+    position: usize
+}
+
+impl Stream for StringInput {
+    fn is_input(&self) -> bool {
+        true
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let rest = &self.bytes[self.position..];
+        let len = rest.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&rest[..len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Writes to a string
+struct StringOutput(Vec<u8>);
+
+impl Stream for StringOutput {
+    fn is_input(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn contents(&self) -> Option<&[u8]> {
+        Some(&self.0)
+    }
+}
+
+/// Reads from a `std::io::Read`, such as a file
+#[cfg(feature = "std")]
+struct Reader<R>(R);
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + Send> Stream for Reader<R> {
+    fn is_input(&self) -> bool {
+        true
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        Ok(self.0.read(buffer)?)
+    }
+}
+
+/// Writes to a `std::io::Write`, such as a file
+#[cfg(feature = "std")]
+struct Writer<W>(W);
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + Send> Stream for Writer<W> {
+    fn is_input(&self) -> bool {
+        false
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        Ok(self.0.write_all(bytes)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.0.flush()?)
+    }
+}
+
+/// A source or destination of bytes. The stream is closed by `close`,
+/// or else when the collector frees the port or `Memory::destroy` runs.
+/// A memory that is dropped without `destroy` never closes its ports,
+/// which loses what the buffered ones did not write yet.
+///
+/// The stream lives outside of the memory, so the port is a raw chunk
+/// holding the box of the stream, which is moved along with the chunk.
+#[derive(ChunkContent)]
+#[tag(18)]
+// otherwise the niche of the box may move the stream in front of the header
+#[repr(C)]
+pub struct Port {
+    _hdr: Header,
+    /// The stream, or nothing once the port is closed
+    stream: Option<Box<dyn Stream>>,
+    input: u64
+}
+
+impl Port {
+    /// Allocates a port over the stream
+    pub fn new<'t, B: Backing>(mem: &'t Memory<'t, B>, stream: Box<dyn Stream>) -> Result<MemPtr<'t, Port>> {
+        mem.register_finalizer::<Port>();
+        let ptr = mem.allocate_raw::<Port>(0)?;
+        ptr.modify::<Port>(|port| unsafe {
+            // SAFETY: the cells of the new chunk are not initialized,
+            // so they must not be dropped as a stream
+            core::ptr::write(port, Port { _hdr: port._hdr, input: stream.is_input() as u64, stream: Some(stream) });
+        });
+        ptr.downcast()
+    }
+
+    /// Allocates a port reading the string
+    pub fn input_string<'t, B: Backing>(mem: &'t Memory<'t, B>, s: &str) -> Result<MemPtr<'t, Port>> {
+        Port::new(mem, Box::new(StringInput { bytes: s.as_bytes().to_vec(), position: 0 }))
+    }
+
+    /// Allocates a port writing to a string, see `MemPtr::<Port>::contents`
+    pub fn output_string<'t, B: Backing>(mem: &'t Memory<'t, B>) -> Result<MemPtr<'t, Port>> {
+        Port::new(mem, Box::new(StringOutput(Vec::new())))
+    }
+
+    /// Allocates a port reading the standard input
+    #[cfg(feature = "std")]
+    pub fn stdin<'t, B: Backing>(mem: &'t Memory<'t, B>) -> Result<MemPtr<'t, Port>> {
+        Port::new(mem, Box::new(Reader(std::io::stdin())))
+    }
+
+    /// Allocates a port writing to the standard output
+    #[cfg(feature = "std")]
+    pub fn stdout<'t, B: Backing>(mem: &'t Memory<'t, B>) -> Result<MemPtr<'t, Port>> {
+        Port::new(mem, Box::new(Writer(std::io::stdout())))
+    }
+
+    /// Opens the file for reading
+    #[cfg(feature = "std")]
+    pub fn open_input_file<'t, B: Backing>(mem: &'t Memory<'t, B>, path: impl AsRef<std::path::Path>) -> Result<MemPtr<'t, Port>> {
+        let file = std::fs::File::open(path)?;
+        Port::new(mem, Box::new(Reader(std::io::BufReader::new(file))))
+    }
+
+    /// Creates or truncates the file for writing. The writes are buffered,
+    /// so the port has to be closed, by `close` or else by `Memory::destroy`,
+    /// for all of them to reach the file.
+    #[cfg(feature = "std")]
+    pub fn open_output_file<'t, B: Backing>(mem: &'t Memory<'t, B>, path: impl AsRef<std::path::Path>) -> Result<MemPtr<'t, Port>> {
+        let file = std::fs::File::create(path)?;
+        Port::new(mem, Box::new(Writer(std::io::BufWriter::new(file))))
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input != 0
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+}
+
+impl Finalize for Port {
+    fn finalize(&mut self) {
+        // flushes and closes the stream, there is no one left to report errors to
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.flush();
+        }
+    }
+}
+
+impl MemPtr<'_, Port> {
+    /// Returns the stream, fails if the port is closed
+    #[allow(clippy::mut_from_ref)]
+    fn stream(&self) -> Result<&mut Box<dyn Stream>> {
+        self.cast_mut::<Port>()?.stream.as_mut().ok_or_else(|| anyhow!("port is closed"))
+    }
+
+    /// Reads into the buffer, returns the number of bytes read,
+    /// which is zero at the end of the stream
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize> {
+        self.stream()?.read(buffer)
+    }
+
+    pub fn write(&self, bytes: &[u8]) -> Result<()> {
+        self.stream()?.write(bytes)
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.stream()?.flush()
+    }
+
+    /// Returns the bytes written so far to a port writing to a string
+    pub fn contents(&self) -> Result<Vec<u8>> {
+        self.stream()?.contents().map(<[u8]>::to_vec).ok_or_else(|| anyhow!("not a string port"))
+    }
+
+    /// Flushes and closes the stream, closing a closed port does nothing
+    pub fn close(&self) -> Result<()> {
+        match self.cast_mut::<Port>()?.stream.take() {
+            Some(mut stream) => stream.flush(),
+            None => Ok(())
+        }
+    }
+}
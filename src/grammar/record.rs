@@ -0,0 +1,150 @@
+use alloc::vec::Vec;
+
+use anyhow::{anyhow, Result};
+
+use crate::{memory::{Backing, ChunkContent, Header, MemPtr, Memory, Object, Trace}, printer::Printer};
+
+use super::{Symbol, Vector};
+
+/// Describes the records of a type, as defined by `define-record-type`
+#[derive(ChunkContent, Trace)]
+#[tag(16)]
+pub struct RecordType<'t> {
+    _hdr: Header,
+    /// `Symbol` naming the type
+    pub name: MemPtr<'t>,
+    /// `Vector` of the `Symbol`s naming the fields, in order
+    fields: MemPtr<'t>
+}
+
+impl<'t> RecordType<'t> {
+    /// Allocates a record type with the given name and field names
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, name: MemPtr<'t, Symbol<'t>>, fields: &[MemPtr<'t, Symbol<'t>>]) -> Result<MemPtr<'t, RecordType<'t>>> {
+        let vector = Vector::make(mem, fields.len(), None)?;
+        for (slot, field) in vector.slots_mut()?.iter_mut().zip(fields) {
+            slot.set(field.clone().upcast());
+        }
+        mem.new_object::<RecordType>((name.upcast(), vector.upcast()))
+    }
+}
+
+impl<'t> Object for RecordType<'t> {
+    /// The name and the `Vector` of field names
+    type Init = (MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (name, fields): Self::Init) -> Self {
+        RecordType { _hdr: hdr, name, fields }
+    }
+}
+
+impl<'t> MemPtr<'t, RecordType<'t>> {
+    /// Returns the names of the fields, in order
+    pub fn field_names(&self) -> Result<Vec<MemPtr<'t>>> {
+        let vector = self.fields.downcast::<Vector>()?;
+        (0..vector.len()).map(|i| vector.ref_(i)).collect()
+    }
+
+    /// Number of fields of the records of this type
+    pub fn field_count(&self) -> Result<usize> {
+        Ok(self.fields.downcast::<Vector>()?.len())
+    }
+
+    /// Returns the index of the named field, fails if there is no such field
+    pub fn field_index(&self, name: &MemPtr<'_, Symbol<'_>>) -> Result<usize> {
+        self.field_names()?.iter().position(|field| *field == name.clone().upcast())
+            .ok_or_else(|| anyhow!("record type {} has no field {}", Printer(&self.name), name.name().unwrap_or("?")))
+    }
+}
+
+/// An instance of a `RecordType`, with the values of
+/// its fields in the cells following its header
+#[derive(ChunkContent)]
+#[tag(17)]
+pub struct Record<'t> {
+    _hdr: Header,
+    /// The `RecordType` of the record
+    pub record_type: MemPtr<'t>,
+    /// Number of fields
+    len: u64
+}
+
+impl<'t> Record<'t> {
+    /// Allocates a record of the given type, with a value for every field
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, record_type: &MemPtr<'t, RecordType<'t>>, values: &[MemPtr<'t>]) -> Result<MemPtr<'t, Record<'t>>> {
+        let count = record_type.field_count()?;
+        if values.len() != count {
+            return Err(anyhow!("record type {} has {} fields, not {}", Printer(&record_type.name), count, values.len()));
+        }
+        let ptr = mem.allocate::<Record>(isize::try_from(count)?)?;
+        ptr.modify::<Record>(|record| {
+            record.record_type = record_type.clone().upcast();
+            record.len = count as u64;
+        });
+        let ptr = ptr.downcast::<Record>()?;
+        ptr.fields_mut()?.clone_from_slice(values);
+        Ok(ptr)
+    }
+
+    /// Number of fields of the record
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if the record has no fields
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Trace for Record<'_> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        self.record_type.trace(visitor);
+        let len = self.len();
+        let fields = unsafe {
+            // SAFETY: the fields follow the fixed cells, `len` of them
+            core::slice::from_raw_parts_mut((self as *mut Record).add(1) as *mut MemPtr<'_>, len)
+        };
+        fields.trace(visitor)
+    }
+}
+
+impl<'t> MemPtr<'t, Record<'t>> {
+    /// Returns the values of the fields, in order
+    pub fn fields(&self) -> Result<&[MemPtr<'t>]> {
+        let cells = self.tail_slice::<Record>()?;
+        Ok(unsafe {
+            // SAFETY: a pointer is a single cell
+            core::slice::from_raw_parts(cells.as_ptr() as *const MemPtr<'t>, cells.len())
+        })
+    }
+
+    /// Same as `fields` but returns an exclusive mutable slice,
+    /// fails if the record is frozen
+    #[allow(clippy::mut_from_ref)]
+    fn fields_mut(&self) -> Result<&mut [MemPtr<'t>]> {
+        let cells = self.tail_slice_mut::<Record>()?;
+        Ok(unsafe {
+            // SAFETY: see `fields`
+            core::slice::from_raw_parts_mut(cells.as_mut_ptr() as *mut MemPtr<'t>, cells.len())
+        })
+    }
+
+    /// Returns true if the record is of the given type
+    pub fn is_a(&self, record_type: &MemPtr<'_, RecordType<'_>>) -> bool {
+        self.record_type == record_type.clone().upcast()
+    }
+
+    /// Returns the value of the field at the index, fails if it is out of range
+    pub fn field(&self, index: usize) -> Result<MemPtr<'t>> {
+        self.fields()?.get(index).cloned()
+            .ok_or_else(|| anyhow!("index {} out of range for a record of {} fields", index, self.len()))
+    }
+
+    /// Stores the value in the field at the index, fails if it is out of range
+    pub fn set_field(&self, index: usize, value: MemPtr<'t>) -> Result<()> {
+        let len = self.len();
+        *self.fields_mut()?.get_mut(index)
+            .ok_or_else(|| anyhow!("index {} out of range for a record of {} fields", index, len))? = value;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use alloc::{string::String, vec::Vec};
+
+use anyhow::Result;
+
+use crate::memory::{Backing, ChunkContent, Header, MemPtr, Memory, Object, Trace};
+
+use super::{Pair, Str, Vector};
+
+/// A datum as the reader read it, with where it was read from, so the
+/// expander can point at the source when it reports an error. The
+/// components of a list or vector that was read are syntax objects too.
+#[derive(ChunkContent, Trace)]
+#[tag(22)]
+pub struct Syntax<'t> {
+    _hdr: Header,
+    pub datum: MemPtr<'t>,
+    /// `Str` naming the file, or the empty list if it was not read from a file
+    pub source: MemPtr<'t>,
+    /// Line, counting from 1
+    line: u64,
+    /// Column, counting from 1
+    column: u64
+}
+
+impl<'t> Syntax<'t> {
+    /// Allocates a syntax object for a datum read at the given line and column
+    pub fn new<B: Backing>(mem: &'t Memory<'t, B>, datum: MemPtr<'t>, source: MemPtr<'t>, line: usize, column: usize) -> Result<MemPtr<'t, Syntax<'t>>> {
+        mem.new_object::<Syntax>((datum, source, line, column))
+    }
+
+    pub fn line(&self) -> usize {
+        self.line as usize
+    }
+
+    pub fn column(&self) -> usize {
+        self.column as usize
+    }
+}
+
+impl<'t> Object for Syntax<'t> {
+    /// The datum, the source, the line and the column
+    type Init = (MemPtr<'t>, MemPtr<'t>, usize, usize);
+
+    fn init(hdr: Header, (datum, source, line, column): Self::Init) -> Self {
+        Syntax { _hdr: hdr, datum, source, line: line as u64, column: column as u64 }
+    }
+}
+
+impl<'t> MemPtr<'t, Syntax<'t>> {
+    /// Returns the location as `file:line:column`, or
+    /// `line:column` if the datum was not read from a file
+    pub fn location(&self) -> Result<String> {
+        match self.source.downcast::<Str>() {
+            Ok(file) => Ok(alloc::format!("{}:{}:{}", file.as_str()?, self.line(), self.column())),
+            Err(_) => Ok(alloc::format!("{}:{}", self.line(), self.column()))
+        }
+    }
+}
+
+/// Returns the datum without any syntax objects, copying the
+/// lists and vectors that contain them
+pub fn strip<'t, B: Backing>(mem: &'t Memory<'t, B>, ptr: &MemPtr<'t>) -> Result<MemPtr<'t>> {
+    if let Ok(syntax) = ptr.downcast::<Syntax>() {
+        return strip(mem, &syntax.datum);
+    }
+    if let Ok(pair) = ptr.downcast::<Pair>() {
+        let (car, cdr) = (strip(mem, &pair.car)?, strip(mem, &pair.cdr)?);
+        if car == pair.car && cdr == pair.cdr {
+            return Ok(ptr.clone());
+        }
+        return Ok(Pair::new(mem, car, cdr)?.upcast());
+    }
+    if let Ok(vector) = ptr.downcast::<Vector>() {
+        let slots = vector.slots()?.iter()
+            .map(|slot| slot.get().map(|value| strip(mem, value)).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        if slots.iter().zip(vector.slots()?).all(|(stripped, slot)| stripped.as_ref() == slot.get()) {
+            return Ok(ptr.clone());
+        }
+        let copy = Vector::make(mem, slots.len(), None)?;
+        for (i, value) in slots.into_iter().enumerate() {
+            if let Some(value) = value {
+                copy.set(i, value)?;
+            }
+        }
+        return Ok(copy.upcast());
+    }
+    Ok(ptr.clone())
+}
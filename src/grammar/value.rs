@@ -0,0 +1,93 @@
+use crate::{match_heap, memory::{Bytes, MemPtr}};
+
+use super::{Bignum, Bytevector, Closure, Code, Condition, Continuation, Environment, Flonum, HashTable, Keyword, MBox, Number, Pair, Port, Promise, Rational, Record, RecordType, Str, Symbol, Syntax, Vector};
+
+/// A value by its type, with a typed pointer to its chunk if it has one,
+/// see `MemPtr::classify`
+#[derive(Debug, Clone)]
+pub enum Value<'t> {
+    /// The empty list
+    Null,
+    Fixnum(i64),
+    Bool(bool),
+    Char(char),
+    Eof,
+    Unspecified,
+    Pair(MemPtr<'t, Pair<'t>>),
+    Number(MemPtr<'t, Number>),
+    Bignum(MemPtr<'t, Bignum>),
+    Rational(MemPtr<'t, Rational<'t>>),
+    Flonum(MemPtr<'t, Flonum>),
+    Symbol(MemPtr<'t, Symbol<'t>>),
+    Keyword(MemPtr<'t, Keyword<'t>>),
+    Str(MemPtr<'t, Str>),
+    Vector(MemPtr<'t, Vector>),
+    Bytevector(MemPtr<'t, Bytevector>),
+    HashTable(MemPtr<'t, HashTable<'t>>),
+    Closure(MemPtr<'t, Closure<'t>>),
+    Code(MemPtr<'t, Code<'t>>),
+    Continuation(MemPtr<'t, Continuation<'t>>),
+    Environment(MemPtr<'t, Environment<'t>>),
+    Promise(MemPtr<'t, Promise<'t>>),
+    MBox(MemPtr<'t, MBox<'t>>),
+    RecordType(MemPtr<'t, RecordType<'t>>),
+    Record(MemPtr<'t, Record<'t>>),
+    Port(MemPtr<'t, Port>),
+    Condition(MemPtr<'t, Condition<'t>>),
+    Syntax(MemPtr<'t, Syntax<'t>>),
+    Bytes(MemPtr<'t, Bytes>),
+    /// A chunk of a type of the memory itself, such as an ephemeron,
+    /// or a pointer that does not point to a valid chunk
+    Other(MemPtr<'t>)
+}
+
+impl<'t> MemPtr<'t> {
+    /// Returns the value by its type, so that values can be told apart
+    /// with a `match` instead of trying to `downcast` them one type at a time
+    pub fn classify(&self) -> Value<'t> {
+        if self.is_null() {
+            return Value::Null;
+        }
+        if let Some(n) = self.as_fixnum() {
+            return Value::Fixnum(n);
+        }
+        if let Some(b) = self.as_bool() {
+            return Value::Bool(b);
+        }
+        if let Some(c) = self.as_char() {
+            return Value::Char(c);
+        }
+        if self.is_eof() {
+            return Value::Eof;
+        }
+        if self.is_unspecified() {
+            return Value::Unspecified;
+        }
+        match_heap!(self, {
+            Pair(pair) => Value::Pair(pair),
+            Number(number) => Value::Number(number),
+            Bignum(bignum) => Value::Bignum(bignum),
+            Rational(ratio) => Value::Rational(ratio),
+            Flonum(flonum) => Value::Flonum(flonum),
+            Symbol(symbol) => Value::Symbol(symbol),
+            Keyword(keyword) => Value::Keyword(keyword),
+            Str(str) => Value::Str(str),
+            Vector(vector) => Value::Vector(vector),
+            Bytevector(bytevector) => Value::Bytevector(bytevector),
+            HashTable(table) => Value::HashTable(table),
+            Closure(closure) => Value::Closure(closure),
+            Code(code) => Value::Code(code),
+            Continuation(continuation) => Value::Continuation(continuation),
+            Environment(env) => Value::Environment(env),
+            Promise(promise) => Value::Promise(promise),
+            MBox(cell) => Value::MBox(cell),
+            RecordType(record_type) => Value::RecordType(record_type),
+            Record(record) => Value::Record(record),
+            Port(port) => Value::Port(port),
+            Condition(condition) => Value::Condition(condition),
+            Syntax(syntax) => Value::Syntax(syntax),
+            Bytes(bytes) => Value::Bytes(bytes),
+            _ => Value::Other(self.clone())
+        })
+    }
+}
@@ -0,0 +1,15 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+// lets the derive macros refer to this crate by name
+extern crate self as slip_rs;
+
+/// A linear memory bump allocator with compacting garbage collector.
+pub mod memory;
+pub mod grammar;
+pub mod numeric;
+pub mod printer;
+pub mod diff;
+/// Version and build metadata
+pub mod version;
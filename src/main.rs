@@ -1,6 +0,0 @@
-/// A linear memory bump allocator with compacting garbage collector.
-mod memory;
-
-fn main() {
-    println!("Hello, world!");
-}
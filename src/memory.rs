@@ -1,64 +1,464 @@
-use std::{borrow::{Borrow, BorrowMut}, cell::RefCell, marker::PhantomData};
+use core::{marker::PhantomData, sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering}};
+
+use alloc::vec::Vec;
 
 use anyhow::{anyhow, Result};
 use bitfield_struct::bitfield;
 
-/// A pointer to an untyped memory chunk
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct Ptr<'t> {
+mod backing;
+mod cell;
+#[cfg(feature = "std")]
+mod conservative;
+mod gc;
+mod interior;
+mod intern;
+#[cfg(feature = "std")]
+mod shadow;
+mod sync;
+mod weak;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod mmap;
+#[cfg(feature = "profiling")]
+mod profile;
+
+pub use backing::Backing;
+pub use cell::Cell;
+pub use gc::{GcEvent, GcTrigger};
+pub use interior::Interior;
+#[cfg(feature = "std")]
+pub use shadow::Root;
+pub use weak::WeakTable;
+pub use slip_derive::{ChunkContent, Trace};
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub use mmap::Mmap;
+#[cfg(feature = "profiling")]
+pub use profile::{Profile, Site};
+
+/// A pointer to a memory chunk holding a `T`, or to any chunk
+/// (or an immediate value such as a fixnum) if `T` is `Any`.
+#[repr(transparent)]
+pub struct MemPtr<'t, T = Any> {
     ptr: *const u64,
-    pd: PhantomData<&'t ()>
+    pd: PhantomData<Marker<'t, T>>
+}
+
+/// Ties a pointer to the lifetime of its memory and to the type of
+/// its chunk, without owning a `T` or affecting its auto traits.
+type Marker<'t, T> = (&'t (), fn() -> T);
+
+/// The contents of a chunk of unknown type, of which only the header is known
+pub struct Any {
+    hdr: Header
+}
+
+impl Any {
+    /// Returns the tag of the chunk
+    pub fn tag(&self) -> usize {
+        self.hdr.tag()
+    }
+}
+
+impl<C> core::fmt::Debug for MemPtr<'_, C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("MemPtr").field(&self.ptr).finish()
+    }
+}
+
+impl<C> Clone for MemPtr<'_, C> {
+    fn clone(&self) -> Self {
+        MemPtr { ptr: self.ptr, pd: PhantomData }
+    }
+}
+
+impl<C> PartialEq for MemPtr<'_, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<C> Eq for MemPtr<'_, C> {}
+
+impl<'t, C: ChunkContent> From<MemPtr<'t, C>> for MemPtr<'t> {
+    fn from(ptr: MemPtr<'t, C>) -> MemPtr<'t> {
+        ptr.upcast()
+    }
+}
+
+impl<'t, C: ChunkContent> core::ops::Deref for MemPtr<'t, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        unsafe {
+            // SAFETY: typed pointers are only created by `downcast`,
+            // after checking the tag of the chunk.
+            &*(self.ptr as *const C)
+        }
+    }
+}
+
+impl<'t> MemPtr<'t> {
+    /// The null pointer, which does not point to any chunk. 
+    /// It represents the absence of a value, such as the empty list.
+    pub fn null() -> MemPtr<'t> {
+        MemPtr { ptr: core::ptr::null(), pd: PhantomData }
+    }
+
+    /// Checks that the pointer points to a chunk holding a `T`,
+    /// returns a typed pointer to it that can be dereferenced.
+    pub fn downcast<T: ChunkContent>(&self) -> Result<MemPtr<'t, T>> {
+        if self.header()?.tag() != T::tag() {
+            return Err(anyhow!("invalid memory chunk"));
+        }
+        Ok(MemPtr { ptr: self.ptr, pd: PhantomData })
+    }
+
+    /// Same as `downcast`, but the tag of the chunk is only checked in 
+    /// debug builds, for hot paths where the type was checked before.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to a chunk holding a `T`.
+    pub(crate) unsafe fn downcast_unchecked<T: ChunkContent>(&self) -> MemPtr<'t, T> {
+        debug_assert!(self.header().is_ok_and(|hdr| hdr.tag() == T::tag()), "unchecked downcast of a chunk of another type");
+        MemPtr { ptr: self.ptr, pd: PhantomData }
+    }
 }
 
-impl<'t> Ptr<'t> {
-    /// Returns true if the given reference has the same pointer
-    /// value as the current pointer.
-    fn equal<T>(&self, that: &T) -> bool {
-        std::ptr::eq(self.ptr as *const T, that)
+/// Dispatches on the type of the chunk a pointer points to, binding a
+/// typed pointer to it (see `MemPtr::downcast`) in the first arm whose
+/// type matches. Values that are not chunks, such as fixnums and the
+/// null pointer, and chunks of any other type go to the `_` arm, which
+/// is required:
+///
+/// ```ignore
+/// let n = match_heap!(ptr, {
+///     Pair(pair) => pair.car.as_fixnum(),
+///     Number(number) => Some(number.n),
+///     _ => ptr.as_fixnum()
+/// });
+/// ```
+#[macro_export]
+macro_rules! match_heap {
+    ($ptr:expr, { $($($ty:ident)::+ ($bind:pat) => $body:expr,)* _ => $default:expr $(,)? }) => {{
+        let ptr = &$ptr;
+        $(if let Ok($bind) = ptr.downcast::<$($ty)::+>() { $body } else)* { $default }
+    }};
+}
+
+impl<'t, C> MemPtr<'t, C> {
+    /// Forgets the type of the chunk the pointer points to
+    pub fn upcast(self) -> MemPtr<'t> {
+        MemPtr { ptr: self.ptr, pd: PhantomData }
+    }
+
+    /// Returns the header of the chunk as `Any`
+    pub fn as_any(&self) -> Result<&Any> {
+        self.header()?;
+        unsafe {
+            // SAFETY: the pointer points to an initialized header
+            Ok(&*(self.ptr as *const Any))
+        }
+    }
+
+    /// Returns the tag of the chunk the pointer points to
+    pub fn tag(&self) -> Result<usize> {
+        Ok(self.header()?.tag())
+    }
+
+    /// Returns true if this is the null pointer
+    pub fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    /// Encodes a small integer directly in the pointer word, without 
+    /// allocating a chunk. Chunks are aligned to cells so the lowest bit
+    /// of a pointer to a chunk is always clear, a fixnum has it set and 
+    /// stores the integer in the remaining 63 bits.
+    ///
+    /// Returns `None` if the integer does not fit in 63 bits.
+    pub fn fixnum(n: i64) -> Option<MemPtr<'t>> {
+        (FIXNUM_MIN..=FIXNUM_MAX).contains(&n).then(|| MemPtr { 
+            ptr: core::ptr::without_provenance(((n << 1) | 1) as usize), 
+            pd: PhantomData 
+        })
+    }
+
+    /// Returns true if the integer is encoded in the pointer itself
+    pub fn is_fixnum(&self) -> bool {
+        self.ptr.addr() & 1 == 1
+    }
+
+    /// Returns the integer encoded in the pointer, if any
+    pub fn as_fixnum(&self) -> Option<i64> {
+        self.is_fixnum().then(|| self.ptr.addr() as i64 >> 1)
+    }
+
+    /// Encodes the `n`th special value, such as a boolean, directly in the
+    /// pointer word. Special values have `0b010` in their lowest three bits,
+    /// which neither pointers to chunks nor fixnums do, and `n` above them.
+    ///
+    /// `n` must be positive, as 0 marks an empty `Cell`.
+    pub fn special(n: usize) -> MemPtr<'t> {
+        assert!(n > 0 && n <= usize::MAX >> 3, "invalid special value: {}", n);
+        MemPtr { ptr: core::ptr::without_provenance((n << 3) | SPECIAL), pd: PhantomData }
+    }
+
+    /// Returns the special value encoded in the pointer, if any
+    pub fn as_special(&self) -> Option<usize> {
+        let addr = self.ptr.addr();
+        (addr & 0b111 == SPECIAL && addr >> 3 > 0).then_some(addr >> 3)
     }
 
     /// Applies the given function to the value if the memory chunk
-    /// contains a value of the correct type, otherwise panics.
-    pub fn modify<T: Element>(&self, f: impl FnOnce(&mut T)) {
+    /// contains a value of the correct type and is not frozen, 
+    /// otherwise panics.
+    pub fn modify<T: ChunkContent>(&self, f: impl FnOnce(&mut T)) {
         f(self.cast_mut::<T>().unwrap());
     }
 
     /// Safe casting mechanism, looks at the tag the pointer is pointing to 
     /// returns a shared reference inside a Result which can result in a 
     /// runtime error when casting was not allowed.
-    pub fn cast<T: Element>(&'t self) -> Result<&'t T> {
-        unsafe {
-            // SAFETY: Dereferencing the raw point is safe 
-            // since it is created by the `Memory`. Thus, 
-            // it has an initialized header with the correct types.
-            let hdr: Header = *(self.ptr as *const Header);
-            // safe to convert the chunk to a pointer to 
-            // the required type.
-            println!("found tag: {}, expected tag: {}", hdr.tag(), T::tag());
-            if hdr.tag() == T::tag() {
+    pub fn cast<T: ChunkContent>(&'t self) -> Result<&'t T> {
+        let hdr = self.header()?;
+        if hdr.tag() == T::tag() {
+            unsafe {
+                // SAFETY: the header was initialized by the `Memory`
+                // with the tag of `T`, so the chunk holds a `T`.
                 Ok(&*(self.ptr as *const T))
-            } else {
-                Err(anyhow!("invalid memory chunk"))
             }
+        } else {
+            Err(anyhow!("invalid memory chunk"))
         }
     }
 
     /// Same as `cast` but returns an exclusive mutable reference
-    pub fn cast_mut<T: Element>(&'t self) -> Result<&'t mut T> {
-        unsafe {
-            // SAFETY: Dereferencing the raw point is safe 
-            // since it is created by the `Memory`. Thus, 
-            // it has an initialized header with the correct types.
-            let hdr: Header = *(self.ptr as *const Header);
-            // safe to convert the chunk to a pointer to 
-            // the required type.
-            if hdr.tag() == T::tag() {
+    #[allow(clippy::mut_from_ref)]
+    pub fn cast_mut<T: ChunkContent>(&'t self) -> Result<&'t mut T> {
+        let hdr = self.writable()?;
+        if hdr.tag() == T::tag() {
+            self.remember();
+            unsafe {
+                // SAFETY: see `cast`
                 Ok(&mut *(self.ptr as *mut T))
-            } else {
-                Err(anyhow!("invalid memory chunk"))
             }
+        } else {
+            Err(anyhow!("invalid memory chunk"))
+        }
+    }
+
+    /// Returns the header of the chunk the pointer is pointing to
+    fn header(&self) -> Result<Header> {
+        if self.is_null() {
+            return Err(anyhow!("null pointer"));
+        }
+        if self.is_fixnum() {
+            return Err(anyhow!("immediate fixnum is not a memory chunk"));
+        }
+        if !self.ptr.addr().is_multiple_of(size_of::<u64>()) {
+            return Err(anyhow!("immediate value is not a memory chunk"));
+        }
+        let hdr = unsafe {
+            // SAFETY: the pointer is created by the `Memory`,
+            // so it points to an initialized, aligned header.
+            AtomicU64::from_ptr(self.ptr as *mut u64).load(Ordering::Acquire)
+        };
+        debug_assert!(hdr != POISON, "use of a pointer to a reclaimed chunk: {:p}", self.ptr);
+        Ok(Header::from_bits(hdr))
+    }
+
+    /// Returns a hash of the identity of the value, which does not change 
+    /// when the chunk it points to is moved by the collector. Immediate 
+    /// values are hashed by value, chunks get a hash assigned (stored in 
+    /// their header) the first time they are hashed.
+    ///
+    /// The header only has room for 15 bits, so there are at most 32768
+    /// hashes of chunks, all below `1 << 15`. Tables with more buckets than
+    /// that have to mix the hash before reducing it to a bucket, as
+    /// `HashTable` does, and still see chunks share hashes.
+    pub fn identity_hash(&self) -> u64 {
+        let Ok(hdr) = self.header() else {
+            return mix(self.ptr.addr() as u64);
+        };
+        if hdr.hash() != 0 {
+            return hdr.hash() as u64;
+        }
+        // 15 bits of the mixed address where the chunk is first hashed,
+        // kept when it moves, so other chunks may come to share them
+        let hash = (mix(self.ptr.addr() as u64) as u16 & 0x7fff).max(1);
+        // another thread hashing the chunk at the same time may win,
+        // but it computes the same hash from the same address
+        self.update_header(|hdr| if hdr.hash() == 0 { hdr.with_hash(hash) } else { hdr }).hash() as u64
+    }
+
+    /// Returns the number of collections the chunk survived, 
+    /// saturating at `MAX_AGE`
+    pub fn age(&self) -> Result<u8> {
+        Ok(self.header()?.age())
+    }
+
+    /// Returns whether the chunk was allocated since the previous collection, 
+    /// and is therefore collected by `Memory::collect_minor`, or survived it
+    pub fn generation(&self) -> Result<Generation> {
+        Ok(if self.age()? == 0 { Generation::Young } else { Generation::Old })
+    }
+
+    /// Write barrier: records that the chunk may now point to chunks
+    /// allocated after it, see `Memory::collect_minor`.
+    /// Returns the header of the chunk, 
+    /// fails if it is part of the frozen region.
+    fn writable(&self) -> Result<Header> {
+        let hdr = self.header()?;
+        if hdr.frozen() {
+            return Err(anyhow!("cannot modify a frozen chunk"));
+        }
+        Ok(hdr)
+    }
+
+    /// Returns true if the chunk is part of the frozen region
+    pub fn is_frozen(&self) -> bool {
+        self.header().is_ok_and(|hdr| hdr.frozen())
+    }
+
+    fn remember(&self) {
+        self.update_header(|hdr| hdr.with_remembered(true));
+    }
+
+    /// Replaces the header by `f` of it in one atomic step, so bits set
+    /// by other threads at the same time are not lost, returns the new header
+    fn update_header(&self, f: impl Fn(Header) -> Header) -> Header {
+        let header = unsafe {
+            // SAFETY: only called after `header` succeeded,
+            // so the pointer points to an initialized, aligned header.
+            AtomicU64::from_ptr(self.ptr as *mut u64)
+        };
+        let update = |bits| Some(f(Header::from_bits(bits)).into_bits());
+        // `update` never gives up, so this always succeeds
+        let old = header.fetch_update(Ordering::AcqRel, Ordering::Acquire, update).unwrap_or_else(|old| old);
+        f(Header::from_bits(old))
+    }
+
+    /// Returns the location and length of the cells allocated
+    /// beyond the fixed size of `T` (i.e., the `additional_size`
+    /// passed to `Memory::allocate`).
+    fn tail<T: ChunkContent>(&self) -> Result<(*mut u64, usize)> {
+        let hdr = self.header()?;
+        if hdr.tag() != T::tag() {
+            return Err(anyhow!("invalid memory chunk"));
+        }
+        let fixed = T::size().unsigned_abs();
+        let len = hdr.size().checked_sub(fixed)
+            .ok_or_else(|| anyhow!("chunk of {} cells is smaller than its type", hdr.size()))?;
+        // the tail starts right after the header and the fixed cells,
+        // which are all part of the same chunk.
+        Ok(((self.ptr as *mut u64).wrapping_add(1 + fixed), len))
+    }
+
+    /// Returns the variable-sized tail of a chunk of type `T`,
+    /// bounded by the size recorded in its header.
+    pub fn tail_slice<T: ChunkContent>(&'t self) -> Result<&'t [u64]> {
+        let (start, len) = self.tail::<T>()?;
+        unsafe {
+            // SAFETY: the tail lies within the chunk as recorded
+            // by the header, which was allocated by the `Memory`.
+            Ok(core::slice::from_raw_parts(start, len))
+        }
+    }
+
+    /// Same as `tail_slice` but returns an exclusive mutable slice
+    #[allow(clippy::mut_from_ref)]
+    pub fn tail_slice_mut<T: ChunkContent>(&'t self) -> Result<&'t mut [u64]> {
+        self.writable()?;
+        let (start, len) = self.tail::<T>()?;
+        self.remember();
+        unsafe {
+            // SAFETY: see `tail_slice`
+            Ok(core::slice::from_raw_parts_mut(start, len))
+        }
+    }
+
+    /// Returns the location of the bytes in the tail of a chunk of type `T`,
+    /// fails if there is no room for as many as the chunk claims to hold.
+    fn byte_tail<T: ByteChunk>(&self) -> Result<(*mut u8, usize)> {
+        let (start, cells) = self.tail::<T>()?;
+        let len = unsafe {
+            // SAFETY: `tail` checked the tag, so the chunk holds a `T`
+            (*(self.ptr as *const T)).byte_len()
+        };
+        if len > cells * size_of::<u64>() {
+            return Err(anyhow!("{} bytes do not fit in a tail of {} cells", len, cells));
+        }
+        Ok((start as *mut u8, len))
+    }
+
+    /// Returns the bytes in the tail of a chunk of type `T`, see `ByteChunk`
+    pub fn tail_bytes<T: ByteChunk>(&'t self) -> Result<&'t [u8]> {
+        let (start, len) = self.byte_tail::<T>()?;
+        unsafe {
+            // SAFETY: the bytes lie within the tail, as checked by `byte_tail`,
+            // and any byte is a valid `u8`
+            Ok(core::slice::from_raw_parts(start, len))
+        }
+    }
+
+    /// Same as `tail_bytes` but returns an exclusive mutable slice
+    #[allow(clippy::mut_from_ref)]
+    pub fn tail_bytes_mut<T: ByteChunk>(&'t self) -> Result<&'t mut [u8]> {
+        self.writable()?;
+        let (start, len) = self.byte_tail::<T>()?;
+        unsafe {
+            // SAFETY: see `tail_bytes`
+            Ok(core::slice::from_raw_parts_mut(start, len))
         }
     }
+
+    /// Returns the payload of a `Bytes` chunk
+    pub fn as_bytes(&'t self) -> Result<&'t [u8]> {
+        self.tail_bytes::<Bytes>()
+    }
+
+    /// Same as `as_bytes` but returns an exclusive mutable slice
+    #[allow(clippy::mut_from_ref)]
+    pub fn as_bytes_mut(&'t self) -> Result<&'t mut [u8]> {
+        self.tail_bytes_mut::<Bytes>()
+    }
+}
+
+/// Smallest integer that can be encoded as a fixnum
+pub const FIXNUM_MIN: i64 = i64::MIN >> 1;
+/// Largest integer that can be encoded as a fixnum
+pub const FIXNUM_MAX: i64 = i64::MAX >> 1;
+/// Lowest bits of a special value, see `MemPtr::special`
+const SPECIAL: usize = 0b010;
+
+/// Age at which chunks stop aging, see `MemPtr::age`
+pub const MAX_AGE: u8 = (1 << Header::AGE_BITS) - 1;
+
+/// Generation of a chunk, see `MemPtr::generation`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generation {
+    Young,
+    Old
+}
+
+/// Spreads the bits of `x` over the whole word (the finalizer of splitmix64)
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Pattern the collector writes over reclaimed cells in debug builds.
+/// As a header it claims a raw chunk of over 3 GiB, which no realistic
+/// memory holds, and it reads as a fixnum when it ends up in a pointer field.
+const POISON: u64 = 0xDEAD_BEEF_DEAD_BEEF;
+/// Content of the canary cell following a chunk, see `Memory::set_canaries`
+const CANARY: u64 = 0xC0FF_EE00_C0FF_EE00;
+
+impl Default for MemPtr<'_> {
+    fn default() -> Self {
+        MemPtr::null()
+    }
 }
 
 #[bitfield(u64)]
@@ -69,7 +469,28 @@ pub struct Header {
     is_raw: bool,
     #[bits(7)]
     tag: usize,
-    #[bits(56)]
+    /// Bit set when the chunk was modified since the previous collection
+    #[bits(1)]
+    remembered: bool,
+    /// Bit set by the collector on the chunks it found to be reachable
+    #[bits(1)]
+    marked: bool,
+    /// Bit set by the collector on the chunks that are about to be moved
+    #[bits(1)]
+    forwarded: bool,
+    /// Bit set on the chunks of the frozen region, see `Memory::freeze`
+    #[bits(1)]
+    frozen: bool,
+    /// Bit set when the chunk is followed by a canary cell, see `Memory::set_canaries`
+    #[bits(1)]
+    canary: bool,
+    /// Number of collections the chunk survived, saturating
+    #[bits(4)]
+    age: u8,
+    /// Identity hash of the chunk, 0 until it is first asked for
+    #[bits(15)]
+    hash: u16,
+    #[bits(32)]
     size: usize
 }
 
@@ -77,45 +498,238 @@ impl Header {
     fn initialize(is_raw: bool, tag: usize,  size: impl Into<usize>) -> Header {
         Header::new().with_is_raw(is_raw).with_tag(tag).with_size(size.into())
     }
-}
 
-/// Basic structure of an untyped memory chunk
-pub struct Chunk {
-    hdr: Header, 
+    /// Number of cells taken up by the chunk, including
+    /// its header and its canary cell if it has one
+    fn cells(&self) -> usize {
+        self.size() + 1 + self.canary() as usize
+    }
 }
 
-/// Memory abstraction
-pub struct Memory<'t> {
+/// Memory abstraction.
+///
+/// The free pointer is bumped atomically, so a `Memory` is both `Send` 
+/// and `Sync`: it can be handed off to a worker thread, shared behind 
+/// a mutex, or allocated from by several threads at once.
+///
+/// The cells themselves are provided by a `Backing`, which defaults to 
+/// a borrowed slice.
+pub struct Memory<'t, B: Backing = &'t mut [u64]> {
+    /// First cell of linear
+    start: *mut u64,
     /// Free pointer into linear
-    free_pointer: RefCell<*const u64>,
+    free_pointer: AtomicPtr<u64>,
+    /// Keeps allocations out while the collector moves chunks
+    gate: sync::Gate,
+    /// End of the chunks that survived the previous collection
+    old_top: AtomicPtr<u64>,
+    /// End of the frozen region, see `Memory::freeze`
+    frozen_top: AtomicPtr<u64>,
+    /// Number of collections so far, not counting arenas
+    collections: AtomicUsize,
+    /// One past the last cell of linear
+    end: *const u64,
+    /// Maximum number of bytes in use, see `Memory::set_quota`
+    quota: AtomicUsize,
+    /// Whether new chunks get a canary cell, see `Memory::set_canaries`
+    canaries: AtomicBool,
+    /// For every tag, how to find the pointers in a chunk with that tag
+    tracers: [sync::OnceLock<Tracer>; 1 << Header::TAG_BITS],
+    /// For every tag, how to release what a chunk with that tag holds
+    /// outside of the memory, see `Memory::register_finalizer`
+    finalizers: [sync::OnceLock<Finalizer>; 1 << Header::TAG_BITS],
+    /// Whether any finalizer is registered
+    finalizing: AtomicBool,
+    /// Where to stop scanning the stack, and for which thread, 
+    /// when collecting with conservative roots
+    #[cfg(feature = "std")]
+    stack_base: sync::Mutex<conservative::StackBase>,
+    /// When `collect_if_needed` collects
+    trigger: sync::Mutex<GcTrigger>,
+    /// Callbacks to notify of the progress of collections
+    hooks: sync::Mutex<Vec<gc::Hook>>,
+    /// Chunks interned by name, see `Memory::intern`
+    interned: sync::Mutex<intern::Interned>,
+    /// Allocations made from every call site
+    #[cfg(feature = "profiling")]
+    sites: profile::Sites,
     /// Linear memory map
-    _linear: &'t [u64]
+    _linear: B,
+    pd: PhantomData<&'t ()>
 }
 
+// SAFETY: the free pointer is only updated atomically, and the collector,
+// which moves chunks through `start` and `end`, closes the gate so that no
+// allocation runs alongside it, so sharing the memory is as safe as sharing
+// its backing.
+unsafe impl<B: Backing + Send> Send for Memory<'_, B> {}
+unsafe impl<B: Backing + Sync> Sync for Memory<'_, B> {}
+
 impl<'t> Memory<'t> {
     /// Create a new memory instance with the given array
     /// as its backing storage
     pub fn new<'s : 't>(memory: &'s mut [u64]) -> Memory<'t> {
-        Memory { free_pointer: memory.as_ptr().into(), _linear: memory }
+        Memory::with_backing(memory)
+    }
+}
+
+impl<'t, B: Backing> Memory<'t, B> {
+    /// Create a new memory instance allocating from 
+    /// the cells of the given backing
+    pub fn with_backing(mut backing: B) -> Memory<'t, B> {
+        let cells = backing.cells();
+        let start = cells.as_mut_ptr();
+        let end = cells.as_ptr_range().end;
+        Memory { 
+            start,
+            free_pointer: AtomicPtr::new(start), 
+            gate: sync::Gate::new(),
+            old_top: AtomicPtr::new(start),
+            frozen_top: AtomicPtr::new(start),
+            collections: AtomicUsize::new(0),
+            end, 
+            quota: AtomicUsize::new(usize::MAX),
+            canaries: AtomicBool::new(false),
+            tracers: [const { sync::OnceLock::new() }; 1 << Header::TAG_BITS],
+            finalizers: [const { sync::OnceLock::new() }; 1 << Header::TAG_BITS],
+            finalizing: AtomicBool::new(false),
+            #[cfg(feature = "std")]
+            stack_base: sync::Mutex::new(None),
+            trigger: sync::Mutex::new(GcTrigger::default()),
+            hooks: sync::Mutex::new(Vec::new()),
+            interned: sync::Mutex::new(intern::Interned::default()),
+            #[cfg(feature = "profiling")]
+            sites: profile::Sites::default(),
+            _linear: backing, 
+            pd: PhantomData 
+        }
+    }
+
+    /// Number of cells currently in use, including the headers of the chunks
+    pub fn used(&self) -> usize {
+        (self.free_pointer.load(Ordering::Acquire) as usize - self.start as usize) / size_of::<u64>()
+    }
+
+    /// Limits the memory in use to the given number of bytes, including
+    /// the headers of the chunks. Allocations that would exceed it fail 
+    /// with a quota error, even when linear still has room for them.
+    pub fn set_quota(&self, bytes: usize) {
+        self.quota.store(bytes, Ordering::Release);
+    }
+
+    /// Follows every chunk allocated from now on with a canary cell holding
+    /// a known value, to catch writes past the end of chunks (such as a
+    /// `ChunkContent::size` that is smaller than the type it describes).
+    /// The canaries are checked before every collection, which panics if
+    /// one was overwritten, and by `check_canaries`. This costs a cell per
+    /// chunk, and is meant for debugging.
+    pub fn set_canaries(&self, enabled: bool) {
+        self.canaries.store(enabled, Ordering::Release);
     }
 
-    fn allocate_<T: Element>(&'t self, additional_size: isize, is_raw: bool) -> Ptr<'t> {
-       let size = T::size() + additional_size;
+    /// Fails with the location, tag and size of the first chunk 
+    /// whose canary cell was overwritten, see `set_canaries`
+    pub fn check_canaries(&self) -> Result<()> {
+        for (chunk, hdr) in self.chunks(self.free_pointer.load(Ordering::Acquire)) {
+            if hdr.canary() && unsafe { 
+                // SAFETY: the canary is the last cell of the chunk
+                *chunk.add(hdr.size() + 1) 
+            } != CANARY {
+                return Err(anyhow!("canary overwritten after the chunk at {:p} with tag {} and {} cells", chunk, hdr.tag(), hdr.size()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reclaims every chunk at once, so the memory can be reused for an 
+    /// unrelated computation. Taking the memory exclusively guarantees
+    /// that no pointer into it is still around.
+    pub fn reset(&mut self) {
+        let used = self.used();
+        self.poison(self.start, used);
+        *self.free_pointer.get_mut() = self.start;
+        *self.old_top.get_mut() = self.start;
+        *self.frozen_top.get_mut() = self.start;
+        self.interned.lock().unwrap().clear();
+    }
+
+    /// Iterates over the tag and location of every chunk allocated so far, 
+    /// skipping the fillers left behind by the collector. Chunks are only
+    /// known to be live right after a collection.
+    pub fn iter_chunks(&'t self) -> impl Iterator<Item = (usize, MemPtr<'t>)> {
+        self.chunks(self.free_pointer.load(Ordering::Acquire))
+            .filter(|(_, hdr)| hdr.tag() != Filler::tag())
+            .map(|(chunk, hdr)| (hdr.tag(), MemPtr { ptr: chunk, pd: PhantomData }))
+    }
+
+    /// Reports how much of the used memory is lost to the holes 
+    /// left in front of pinned chunks by the collector.
+    pub fn fragmentation(&self) -> Fragmentation {
+        let top = self.free_pointer.load(Ordering::Acquire);
+        let holes: Vec<usize> = self.chunks(top)
+            .filter(|(_, hdr)| hdr.tag() == Filler::tag())
+            .map(|(_, hdr)| hdr.cells())
+            .collect();
+        let used = self.used();
+        let wasted = holes.iter().sum::<usize>();
+        Fragmentation {
+            largest_allocatable: ((self.end as usize - top as usize) / size_of::<u64>())
+                .saturating_sub(1 + self.canaries.load(Ordering::Acquire) as usize),
+            wasted: if used == 0 { 0.0 } else { 100.0 * wasted as f64 / used as f64 },
+            holes
+        }
+    }
+
+    #[cfg_attr(feature = "profiling", track_caller)]
+    fn allocate_<T: ChunkContent>(&'t self, additional_size: isize, is_raw: bool) -> Result<MemPtr<'t>> {
+       let size = T::size().checked_add(additional_size)
+           .filter(|size| *size >= 0 && (*size as usize) < 1 << Header::SIZE_BITS)
+           .ok_or_else(|| anyhow!("invalid chunk size: {} additional cells", additional_size))?
+           as usize;
+       let canary = self.canaries.load(Ordering::Acquire);
+       let cells = size + 1 + canary as usize;
+       let quota = self.quota.load(Ordering::Acquire);
+       let within_quota = |current: *mut u64| 
+           (current as usize - self.start as usize) + cells * size_of::<u64>() <= quota;
+       // wait for a collection on another thread, and keep the next
+       // one out until the header of the chunk is written
+       let _entered = self.gate.enter();
+       // claim the chunk by atomically bumping the free pointer, 
+       // so that concurrent allocations never hand out the same cells.
+       let current = self.free_pointer
+           .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                // only addresses are compared here, the pointer is not dereferenced
+                let available = (self.end as usize - current as usize) / size_of::<u64>();
+                (cells <= available && within_quota(current)).then(|| current.wrapping_add(cells))
+           })
+           .map_err(|current| if within_quota(current) {
+               anyhow!("out of memory: requested {} cells, {} available",
+                   cells, (self.end as usize - current as usize) / size_of::<u64>())
+           } else {
+               anyhow!("memory quota exceeded: requested {} bytes, {} of {} bytes in use",
+                   cells * size_of::<u64>(), current as usize - self.start as usize, quota)
+           })?;
        unsafe {
-            // SAFETY: this just increases the free pointer with the required size,
-            // no other unsafe operations are performed on this pointer.
-            let current = *self.free_pointer.borrow();
-            *self.free_pointer.borrow_mut() = current.offset(size + 1);
+            // SAFETY: the chunk starting at `current` lies within linear
+            // and was claimed exclusively by the update above, 
+            // no other allocation can write to it.
             // create memory structure
-            let hdr = Header::initialize(is_raw, T::tag(), size.unsigned_abs());
+            let hdr = Header::initialize(is_raw, T::tag(), size).with_canary(canary);
             *(current as *mut Header) = hdr;
-            Ptr { ptr: current, pd: PhantomData }
+            if canary {
+                *current.add(size + 1) = CANARY;
+            }
        }
+       #[cfg(feature = "profiling")]
+       self.sites.record(core::panic::Location::caller(), cells);
+       Ok(MemPtr { ptr: current, pd: PhantomData })
     }
 
     /// Allocate a memory chunk for the given 
     /// type with the given number of cells.
     ///
+    /// Fails if the chunk does not fit in the remaining memory.
+    ///
     /// The returned pointer can only live as long as the memory does,
     /// so that the following code does not compile: 
     ///
@@ -126,70 +740,473 @@ impl<'t> Memory<'t> {
     /// mem.destroy();
     /// println("{:?}", ptr);
     /// ```
-    pub fn allocate<T: Element>(&'t self, additional_size: isize) -> Ptr<'t> {
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn allocate<T: ChunkContent + Trace>(&'t self, additional_size: isize) -> Result<MemPtr<'t>> {
+        self.tracers[T::tag()].get_or_init(|| trace_chunk::<T>);
         self.allocate_::<T>(additional_size, false)
     }
 
-    /// Allocate a raw memory chunk for 
-    /// the given type
-    pub fn allocate_raw<T: Element>(&'t self, additional_size: isize) -> Ptr<'t> {
+    /// Allocate a memory chunk for the given type and initialize 
+    /// every one of its fields, so that the chunk is complete before
+    /// the pointer to it escapes. Chunks from `allocate` hold whatever 
+    /// was in their cells before, until all of their pointers are set.
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn new_object<T: Object>(&'t self, init: T::Init) -> Result<MemPtr<'t, T>> {
+        debug_assert!(size_of::<T>() == (T::size() as usize + 1) * size_of::<u64>());
+        let ptr = self.allocate::<T>(0)?;
+        let hdr = ptr.header()?;
+        unsafe {
+            // SAFETY: the chunk was just allocated with room for a `T`
+            // and nothing else can refer to it yet
+            core::ptr::write(ptr.ptr as *mut T, T::init(hdr, init));
+        }
+        Ok(MemPtr { ptr: ptr.ptr, pd: PhantomData })
+    }
+
+    /// Allocate a raw memory chunk for the given type,
+    /// its contents are never traced by the collector.
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn allocate_raw<T: ChunkContent>(&'t self, additional_size: isize) -> Result<MemPtr<'t>> {
         self.allocate_::<T>(additional_size, true)
     }
 
-    /// Destroy the memory
-    pub fn destroy(self) { }
+    /// Allocate a raw chunk holding `len` bytes,
+    /// rounded up to a whole number of cells.
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn allocate_bytes(&'t self, len: usize) -> Result<MemPtr<'t>> {
+        self.allocate_byte_chunk::<Bytes>(len)
+    }
+
+    /// Allocate a chunk of type `T` with room for `len` bytes in its tail,
+    /// see `ByteChunk`. The bytes are zeroed.
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn allocate_byte_chunk<T: ByteChunk>(&'t self, len: usize) -> Result<MemPtr<'t>> {
+        let cells = isize::try_from(len.div_ceil(size_of::<u64>()))?;
+        let ptr = self.allocate_raw::<T>(cells)?;
+        ptr.tail_slice_mut::<T>()?.fill(0);
+        ptr.modify::<T>(|chunk| chunk.set_byte_len(len));
+        Ok(ptr)
+    }
+
+    /// Allocate an ephemeron: its value is only kept alive by the collector 
+    /// as long as its key is reachable from elsewhere. Once the key is no 
+    /// longer reachable, the collector clears both the key and the value.
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn allocate_ephemeron(&'t self, key: MemPtr<'t>, value: MemPtr<'t>) -> Result<MemPtr<'t>> {
+        Ok(self.new_object::<Ephemeron>((key, value))?.upcast())
+    }
+
+    /// Finalizes the chunks of type `T` the collector frees from now on,
+    /// see `Finalize`
+    pub fn register_finalizer<T: Finalize>(&self) {
+        self.finalizers[T::tag()].get_or_init(|| finalize_chunk::<T>);
+        self.finalizing.store(true, Ordering::Release);
+    }
 
-    /// Garbage collect with the given pointer as roots, requires
-    /// exclusive access to the memory as well as the roots.
-    pub fn collect<T: Element>(&self, roots: &mut (&mut T)) {}
+    /// Destroy the memory, finalizing the chunks that are still in it.
+    /// Dropping the memory does not run the finalizers, since the chunks
+    /// borrow the memory for as long as it lives.
+    pub fn destroy(self) {
+        if self.finalizing.load(Ordering::Acquire) {
+            for (chunk, hdr) in self.chunks(self.free_pointer.load(Ordering::Acquire)) {
+                unsafe {
+                    // SAFETY: the chunk is in use, and is never used again
+                    self.finalize_chunk(chunk, hdr);
+                }
+            }
+        }
+    }
+}
 
+/// Fragmentation of a memory, see `Memory::fragmentation`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragmentation {
+    /// Size of every hole in cells, in address order
+    pub holes: Vec<usize>,
+    /// Size of the largest chunk that can still be allocated, in cells
+    /// excluding the header. Holes are never allocated from.
+    pub largest_allocatable: usize,
+    /// Percentage of the used cells taken up by holes
+    pub wasted: f64
 }
 
 /// A struct can be a memory chunk if the required 
 /// number of cells is known ahead of time.
-pub trait Element {
+pub trait ChunkContent {
     fn size() -> isize;
     fn tag() -> usize;
 }
 
+/// A chunk that is built from the values of all of its fields at once,
+/// see `Memory::new_object`.
+pub trait Object: ChunkContent + Trace + Sized {
+    /// The values of the fields of the chunk, besides its header
+    type Init;
+
+    /// Builds the chunk with the given header and field values
+    fn init(hdr: Header, init: Self::Init) -> Self;
+}
+
+/// A chunk holding resources outside of the memory, such as a file, that
+/// have to be released when the chunk is freed. Finalizers only run for 
+/// the types registered with `Memory::register_finalizer`.
+pub trait Finalize: ChunkContent {
+    /// Releases the resources, called once when the collector frees the 
+    /// chunk or the memory is destroyed. The chunk is unreachable by then,
+    /// so the pointers in it must not be followed.
+    fn finalize(&mut self);
+}
+
+/// A raw chunk holding bytes in the cells of its tail, such as `Bytes`,
+/// with their number stored in the chunk. See `MemPtr::tail_bytes`.
+pub trait ByteChunk: ChunkContent {
+    /// Number of bytes in the tail
+    fn byte_len(&self) -> usize;
+
+    fn set_byte_len(&mut self, len: usize);
+}
+
+/// A chunk whose pointers can be enumerated precisely,
+/// so that the collector never has to guess which cells are pointers.
+///
+/// Containers of pointers implement it as well, so that they 
+/// can be passed as the roots of a collection.
+pub trait Trace {
+    /// Calls the visitor with every pointer stored in `self`, 
+    /// the visitor may update the pointer in place.
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>));
+}
+
+/// Type-erased `Trace::trace` for the chunks of a single tag
+type Tracer = unsafe fn(*mut u64, &mut dyn FnMut(&mut MemPtr<'_>));
+
+/// Type-erased `Finalize::finalize` for the chunks of a single tag
+type Finalizer = unsafe fn(*mut u64);
+
+/// # Safety
+///
+/// `chunk` must point to an initialized chunk holding a `T`.
+unsafe fn finalize_chunk<T: Finalize>(chunk: *mut u64) {
+    (*(chunk as *mut T)).finalize()
+}
+
+/// # Safety
+///
+/// `chunk` must point to an initialized chunk holding a `T`.
+unsafe fn trace_chunk<T: Trace>(chunk: *mut u64, mut visitor: &mut dyn FnMut(&mut MemPtr<'_>)) {
+    (*(chunk as *mut T)).trace(&mut visitor)
+}
+
+impl<C> Trace for MemPtr<'_, C> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        visitor(unsafe {
+            // SAFETY: the type of a pointer does not change its layout, and 
+            // the collector only moves the chunk, so it still holds a `C`
+            &mut *(self as *mut Self).cast::<MemPtr<'_>>()
+        })
+    }
+}
+
+impl<T: Trace + ?Sized> Trace for &mut T {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        (**self).trace(visitor)
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        if let Some(t) = self {
+            t.trace(visitor)
+        }
+    }
+}
+
+impl<T: Trace> Trace for [T] {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        self.iter_mut().for_each(|t| t.trace(visitor))
+    }
+}
+
+impl<T: Trace, const N: usize> Trace for [T; N] {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        self.as_mut_slice().trace(visitor)
+    }
+}
+
+impl<T: Trace> Trace for Vec<T> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        self.as_mut_slice().trace(visitor)
+    }
+}
+
+macro_rules! trace_nothing {
+    ($($ty:ty)*) => {
+        $(impl Trace for $ty {
+            fn trace(&mut self, _visitor: &mut impl FnMut(&mut MemPtr<'_>)) {}
+        })*
+    };
+}
+
+trace_nothing!(Header u64 i64 usize isize f64);
+
+macro_rules! trace_tuple {
+    ($($name:ident)*) => {
+        impl<$($name: Trace),*> Trace for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+                let ($($name,)*) = self;
+                $($name.trace(visitor);)*
+            }
+        }
+    };
+}
+
+trace_tuple!();
+trace_tuple!(A);
+trace_tuple!(A B);
+trace_tuple!(A B C);
+trace_tuple!(A B C D);
+
+/// Tags from this value up are reserved for the 
+/// elements provided by the memory module itself.
+pub const RESERVED_TAGS: usize = 120;
+
+/// Raw chunk covering the cells left free in front of a chunk 
+/// that could not be moved by the collector.
+pub struct Filler {
+    _hdr: Header
+}
+
+impl ChunkContent for Filler {
+    fn size() -> isize { 0 }
+    fn tag() -> usize { RESERVED_TAGS + 1 }
+}
+
+/// A key/value pair whose value is only reachable through the 
+/// ephemeron if its key is reachable by other means.
+pub struct Ephemeron<'t> {
+    _hdr: Header,
+    key: MemPtr<'t>,
+    value: MemPtr<'t>
+}
+
+impl ChunkContent for Ephemeron<'_> {
+    fn size() -> isize { 2 }
+    fn tag() -> usize { RESERVED_TAGS + 2 }
+}
+
+/// Only used when updating pointers after marking, 
+/// the marking itself handles ephemerons separately.
+impl Trace for Ephemeron<'_> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        visitor(&mut self.key);
+        visitor(&mut self.value);
+    }
+}
+
+impl<'t> Object for Ephemeron<'t> {
+    type Init = (MemPtr<'t>, MemPtr<'t>);
+
+    fn init(hdr: Header, (key, value): Self::Init) -> Self {
+        Ephemeron { _hdr: hdr, key, value }
+    }
+}
+
+impl<'t> Ephemeron<'t> {
+    /// The key, or the null pointer if the key has been collected
+    pub fn key(&self) -> &MemPtr<'t> {
+        &self.key
+    }
+
+    /// The value, or the null pointer if the key has been collected
+    pub fn value(&self) -> &MemPtr<'t> {
+        &self.value
+    }
+
+    /// Returns true if the key has been collected
+    pub fn is_broken(&self) -> bool {
+        self.key.is_null()
+    }
+}
+
+/// A raw buffer addressed at byte granularity.
+///
+/// The header records the size in cells, so the exact 
+/// length in bytes is stored in the first cell of the chunk,
+/// followed by the bytes themselves.
+pub struct Bytes {
+    _hdr: Header,
+    len: u64
+}
+
+impl ChunkContent for Bytes {
+    fn size() -> isize { 1 }
+    fn tag() -> usize { RESERVED_TAGS }
+}
+
+impl ByteChunk for Bytes {
+    fn byte_len(&self) -> usize {
+        self.len()
+    }
+
+    fn set_byte_len(&mut self, len: usize) {
+        self.len = len as u64;
+    }
+}
+
+impl Bytes {
+    /// Number of bytes in the buffer
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if the buffer holds no bytes
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(feature = "std")]
+    use crate::root;
     
+    #[derive(ChunkContent)]
+    #[tag(2)]
     struct Number {
         _hdr: Header, 
         n: u64
     }
 
-    impl Element for Number {
-        fn size() -> isize { 1 }
-        fn tag() -> usize { 2 }
+    #[derive(ChunkContent, Trace)]
+    #[tag(1)]
+    struct Pair<'t> {
+        _hdr: Header,
+        car: MemPtr<'t>,
+        cdr: MemPtr<'t>
     }
 
+    impl<'t> Object for Pair<'t> {
+        type Init = (MemPtr<'t>, MemPtr<'t>);
 
-    struct Pair<'t> {
+        fn init(hdr: Header, (car, cdr): Self::Init) -> Self {
+            Pair { _hdr: hdr, car, cdr }
+        }
+    }
+
+    /// A chunk mixing pointers and plain data
+    #[derive(ChunkContent, Trace)]
+    #[tag(3)]
+    struct Tagged<'t> {
         _hdr: Header,
-        car: Ptr<'t>,
-        cdr: Ptr<'t>
+        label: i64,
+        value: MemPtr<'t>,
+        weight: f64
     }
 
-    impl<'t> Element for Pair<'t> {
-        fn size() -> isize { 2 }
-        fn tag() -> usize { 1 }
+    #[test]
+    fn test_derived_element() {
+        assert!(Number::size() == 1 && Number::tag() == 2);
+        assert!(Pair::size() == 2 && Pair::tag() == 1);
+        assert!(Tagged::size() == 3 && Tagged::tag() == 3);
+
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut tagged = mem.allocate::<Tagged>(0).unwrap();
+        tagged.modify::<Tagged>(|t| {
+            t.label = -1;
+            t.value = cons(&mem, <MemPtr>::fixnum(1).unwrap(), MemPtr::null());
+            t.weight = 0.5;
+        });
+        cons(&mem, MemPtr::null(), MemPtr::null());
+        mem.collect(&mut tagged);
+        assert!(mem.used() == 4 + 3);
+        let t = tagged.cast::<Tagged>().unwrap();
+        assert!(t.label == -1 && t.weight == 0.5);
+        assert!(t.value.cast::<Pair>().unwrap().car.as_fixnum() == Some(1));
     }
 
     #[test]
     fn test_ptr_size() {
         // ensure that the pointer size is 8 bytes (i.e., 64 bits)
-        assert!(size_of::<Ptr<'static>>() == 8)
+        assert!(size_of::<MemPtr<'static>>() == 8);
+        assert!(size_of::<MemPtr<'static, Pair<'static>>>() == 8);
+        assert!(size_of::<Cell<'static>>() == 8)
+    }
+
+    #[test]
+    fn test_typed_pointers() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let ptr = cons(&mem, <MemPtr>::fixnum(1).unwrap(), MemPtr::null());
+        let pair = ptr.downcast::<Pair>().unwrap();
+        assert!(pair.car.as_fixnum() == Some(1) && pair.cdr.is_null());
+        assert!(pair.tag().unwrap() == Pair::tag());
+        assert!(pair.as_any().unwrap().tag() == Pair::tag());
+        assert!(ptr.downcast::<Tagged>().is_err());
+        assert!(MemPtr::from(pair.clone()) == ptr && pair.upcast() == ptr);
+        assert!(<MemPtr>::fixnum(1).unwrap().downcast::<Pair>().is_err());
+    }
+
+    #[test]
+    fn test_match_heap() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let describe = |ptr: &MemPtr| match_heap!(ptr, {
+            Pair(pair) => format!("pair of {:?}", pair.car.as_fixnum()),
+            self::Number(number) => format!("number {}", number.n),
+            _ => format!("other {:?}", ptr.tag().ok())
+        });
+        assert!(describe(&cons(&mem, <MemPtr>::fixnum(1).unwrap(), MemPtr::null())) == "pair of Some(1)");
+        assert!(describe(&number(&mem, 2)) == "number 2");
+        assert!(describe(&mem.allocate::<Tagged>(0).unwrap()) == "other Some(3)");
+        assert!(describe(&<MemPtr>::fixnum(4).unwrap()) == "other None");
+        assert!(describe(&MemPtr::null()) == "other None");
+    }
+
+    /// A chunk holding a variable that may be unbound
+    #[derive(ChunkContent, Trace)]
+    #[tag(4)]
+    struct Slot<'t> {
+        _hdr: Header,
+        value: Cell<'t>
+    }
+
+    #[test]
+    fn test_cells() {
+        let mut cell: Cell = Cell::empty();
+        assert!(cell.is_empty() && cell.get().is_none() && cell == Cell::default());
+        cell.set(<MemPtr>::fixnum(3).unwrap());
+        assert!(cell.as_fixnum() == Some(3));
+        assert!(cell.take() == Some(<MemPtr>::fixnum(3).unwrap()) && cell.is_empty());
+
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut slots = [ mem.allocate::<Slot>(0).unwrap(), mem.allocate::<Slot>(0).unwrap() ];
+        slots[0].modify::<Slot>(|s| s.value = Cell::empty());
+        number(&mem, 1);
+        let n = number(&mem, 2);
+        slots[1].modify::<Slot>(|s| s.value = Cell::new(n));
+        mem.collect(&mut slots);
+        assert!(mem.used() == 2 + 2 + 2);
+        assert!(slots[0].cast::<Slot>().unwrap().value.is_empty());
+        assert!(slots[1].cast::<Slot>().unwrap().value.cast::<Number>().unwrap().n == 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "empty cell")]
+    fn test_empty_cell_deref() {
+        let cell: Cell = Cell::empty();
+        cell.is_null();
     }
 
     #[test]
     fn test_pair() {
         let mut data: [u64 ; 1000] = [ 0 ; 1000 ];
         let mem = Memory::new(&mut data);
-        let ptr = mem.allocate::<Pair>(0);
-        let n = mem.allocate_raw::<Number>(0);
+        let ptr = mem.allocate::<Pair>(0).unwrap();
+        let n = mem.allocate_raw::<Number>(0).unwrap();
         n.modify::<Number>(|nv| {
             nv.n = 42
         });
@@ -203,4 +1220,823 @@ mod test {
         assert!(pai.cdr == n);
         assert!(pai.car.cast::<Number>().unwrap().n == 42);
     }
+
+    #[test]
+    fn test_new_object() {
+        // cells that would crash the collector if they were taken for pointers
+        let mut data: [u64 ; 100] = [ 0x1000 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let n = number(&mem, 7);
+        let mut pair = mem.new_object::<Pair>((n.clone(), MemPtr::null())).unwrap();
+        assert!(pair.car == n && pair.cdr.is_null());
+        assert!(pair.tag().unwrap() == Pair::tag() && mem.used() == 2 + 3);
+        mem.allocate_raw::<Number>(0).unwrap();
+        mem.collect(&mut pair);
+        assert!(mem.used() == 2 + 3);
+        assert!(pair.car.cast::<Number>().unwrap().n == 7);
+    }
+
+    #[test]
+    fn test_null() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let n = mem.allocate_raw::<Number>(0).unwrap();
+        // a one element list, terminated by the empty list
+        let list = mem.allocate::<Pair>(0).unwrap();
+        list.modify::<Pair>(|pai| {
+            pai.car = n.clone();
+            pai.cdr = MemPtr::null();
+        });
+
+        let pai = list.cast::<Pair>().unwrap();
+        assert!(!pai.car.is_null());
+        assert!(pai.cdr.is_null());
+        assert!(pai.cdr == MemPtr::default());
+        assert!(pai.cdr.cast::<Pair>().is_err());
+        assert!(pai.cdr.cast_mut::<Number>().is_err());
+        assert!(pai.cdr.tail_slice::<Pair>().is_err());
+        assert!(pai.cdr.as_bytes().is_err());
+    }
+
+    #[test]
+    fn test_fixnum() {
+        for n in [0, 1, -1, 42, -42, FIXNUM_MIN, FIXNUM_MAX] {
+            let ptr = <MemPtr>::fixnum(n).unwrap();
+            assert!(ptr.is_fixnum());
+            assert!(!ptr.is_null());
+            assert!(ptr.as_fixnum() == Some(n));
+            assert!(ptr.cast::<Number>().is_err());
+            assert!(ptr.tail_slice::<Number>().is_err());
+        }
+        assert!(<MemPtr>::fixnum(FIXNUM_MAX + 1).is_none());
+        assert!(<MemPtr>::fixnum(FIXNUM_MIN - 1).is_none());
+        assert!(<MemPtr>::fixnum(7) == <MemPtr>::fixnum(7));
+        assert!(MemPtr::null().as_fixnum().is_none());
+
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let pair = mem.allocate::<Pair>(0).unwrap();
+        assert!(!pair.is_fixnum());
+        pair.modify::<Pair>(|pai| {
+            pai.car = <MemPtr>::fixnum(1).unwrap();
+            pai.cdr = <MemPtr>::fixnum(-2).unwrap();
+        });
+        let pai = pair.cast::<Pair>().unwrap();
+        assert!(pai.car.as_fixnum().unwrap() + pai.cdr.as_fixnum().unwrap() == -1);
+    }
+
+    #[test]
+    fn test_special() {
+        for n in [1, 2, 42, usize::MAX >> 3] {
+            let ptr = <MemPtr>::special(n);
+            assert!(ptr.as_special() == Some(n));
+            assert!(!ptr.is_null() && !ptr.is_fixnum());
+            assert!(ptr.tag().is_err() && ptr.cast::<Number>().is_err());
+        }
+        assert!(<MemPtr>::special(1) != <MemPtr>::special(2));
+        assert!(<MemPtr>::fixnum(1).unwrap().as_special().is_none());
+        assert!(MemPtr::null().as_special().is_none());
+
+        // special values are left alone by the collector
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut pair = cons(&mem, <MemPtr>::special(3), MemPtr::null());
+        number(&mem, 1);
+        let mut roots = (pair.clone(), <MemPtr>::special(4));
+        mem.collect(&mut roots);
+        (pair, _) = roots;
+        assert!(pair.cast::<Pair>().unwrap().car.as_special() == Some(3));
+    }
+
+    #[test]
+    fn test_canaries() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        number(&mem, 1);
+        mem.set_canaries(true);
+        let mut pair = cons(&mem, MemPtr::null(), MemPtr::null());
+        mem.allocate_bytes(8).unwrap();
+        assert!(mem.used() == 3 + 4 + 3 && mem.fragmentation().largest_allocatable == 100 - 10 - 2);
+        assert!(mem.check_canaries().is_ok());
+
+        // the canaries move along with their chunks
+        mem.collect(&mut pair);
+        assert!(mem.used() == 4 && mem.check_canaries().is_ok());
+        assert!(pair.cast::<Pair>().unwrap().car.is_null());
+
+        // chunks allocated without a canary are not checked
+        mem.set_canaries(false);
+        number(&mem, 2);
+        mem.set_canaries(true);
+        let bytes = mem.allocate_bytes(8).unwrap();
+        unsafe {
+            // one byte too far
+            *bytes.as_bytes_mut().unwrap().as_mut_ptr().add(8) = 1;
+        }
+        let error = mem.check_canaries().unwrap_err().to_string();
+        assert!(error.contains(&format!("{:p}", bytes.ptr)) && error.contains(&format!("tag {}", Bytes::tag())));
+        let collected = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| mem.collect(&mut pair)));
+        assert!(collected.is_err());
+    }
+
+    #[test]
+    fn test_identity_hash() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        number(&mem, 1);
+        let mut pair = cons(&mem, MemPtr::null(), MemPtr::null());
+        let hash = pair.identity_hash();
+        assert!(hash != 0 && pair.identity_hash() == hash);
+        mem.collect(&mut pair);
+        // the pair moved, but kept its hash
+        assert!(mem.used() == 3 && pair.identity_hash() == hash);
+        assert!(pair.cast::<Pair>().is_ok());
+
+        assert!(<MemPtr>::fixnum(3).unwrap().identity_hash() == <MemPtr>::fixnum(3).unwrap().identity_hash());
+        assert!(<MemPtr>::fixnum(3).unwrap().identity_hash() != <MemPtr>::fixnum(4).unwrap().identity_hash());
+    }
+
+    #[test]
+    fn test_tail_slice() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let n = mem.allocate_raw::<Number>(3).unwrap();
+        let next = mem.allocate_raw::<Number>(0).unwrap();
+        next.modify::<Number>(|nv| nv.n = 7);
+
+        n.tail_slice_mut::<Number>().unwrap().copy_from_slice(&[1, 2, 3]);
+        assert!(n.tail_slice::<Number>().unwrap() == [1, 2, 3]);
+        // writing the tail does not touch the fixed part or the next chunk
+        assert!(n.cast::<Number>().unwrap().n == 0);
+        assert!(next.cast::<Number>().unwrap().n == 7);
+
+        assert!(next.tail_slice::<Number>().unwrap().is_empty());
+        assert!(n.tail_slice::<Pair>().is_err());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let bytes = mem.allocate_bytes(11).unwrap();
+        let next = mem.allocate_raw::<Number>(0).unwrap();
+        next.modify::<Number>(|nv| nv.n = u64::MAX);
+
+        assert!(bytes.cast::<Bytes>().unwrap().len() == 11);
+        assert!(bytes.tail_slice::<Bytes>().unwrap().len() == 2);
+        bytes.as_bytes_mut().unwrap().copy_from_slice(b"hello world");
+        assert!(bytes.as_bytes().unwrap() == b"hello world");
+        assert!(next.cast::<Number>().unwrap().n == u64::MAX);
+
+        assert!(mem.allocate_bytes(0).unwrap().as_bytes().unwrap().is_empty());
+        assert!(next.as_bytes().is_err());
+        // a length beyond the tail is never read
+        bytes.modify::<Bytes>(|b| b.len = 17);
+        assert!(bytes.as_bytes().is_err());
+    }
+
+    #[test]
+    fn test_interior_pointers() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        number(&mem, 1);
+        let bytes = mem.allocate_bytes(11).unwrap();
+        bytes.as_bytes_mut().unwrap().copy_from_slice(b"hello world");
+        let vector = mem.allocate_raw::<Number>(4).unwrap();
+        vector.tail_slice_mut::<Number>().unwrap().copy_from_slice(&[1, 2, 3, 4]);
+
+        let mut views = (bytes.substring(6..11).unwrap(), vector.interior::<Number>(1..3).unwrap());
+        assert!(views.0.as_bytes().unwrap() == b"world" && views.0.as_cells().is_err());
+        assert!(views.1.as_cells().unwrap() == [2, 3] && views.1.len() == 16);
+        assert!(bytes.substring(6..12).is_err() && vector.interior::<Number>(3..5).is_err());
+        assert!(bytes.interior::<Number>(0..1).is_err());
+
+        // the views keep their bases alive, and follow them when they move
+        mem.collect(&mut views);
+        assert!(mem.used() == 4 + 6);
+        assert!(views.0.as_bytes().unwrap() == b"world");
+        assert!(views.1.as_cells().unwrap() == [2, 3]);
+        assert!(views.1.base().tail_slice::<Number>().unwrap() == [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_out_of_memory() {
+        let mut data: [u64 ; 5] = [ 0 ; 5 ];
+        let mem = Memory::new(&mut data);
+        let n = mem.allocate_raw::<Number>(0).unwrap();
+        n.modify::<Number>(|nv| nv.n = 42);
+        // a pair needs three cells, only three are left
+        let p = mem.allocate::<Pair>(0).unwrap();
+        assert!(mem.allocate_raw::<Number>(0).is_err());
+        assert!(mem.allocate_bytes(1).is_err());
+        assert!(n.cast::<Number>().unwrap().n == 42);
+        assert!(p.cast::<Pair>().is_ok());
+    }
+
+    #[test]
+    fn test_quota() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        mem.set_quota(5 * 8);
+        let mut n = number(&mem, 1);
+        cons(&mem, MemPtr::null(), MemPtr::null());
+        let err = mem.allocate_raw::<Number>(0).unwrap_err();
+        assert!(err.to_string().starts_with("memory quota exceeded"));
+
+        // collecting frees up room within the quota
+        mem.collect(&mut n);
+        assert!(mem.used() == 2);
+        assert!(mem.allocate::<Pair>(0).is_ok());
+        assert!(mem.allocate_raw::<Number>(0).is_err());
+        mem.set_quota(usize::MAX);
+        assert!(mem.allocate_raw::<Number>(0).is_ok());
+    }
+
+    #[test]
+    fn test_oversized_allocation() {
+        let mut data: [u64 ; 5] = [ 0 ; 5 ];
+        let mem = Memory::new(&mut data);
+        assert!(mem.allocate::<Pair>(3).is_err());
+        assert!(mem.allocate::<Pair>(isize::MAX).is_err());
+        assert!(mem.allocate::<Pair>(-3).is_err());
+        assert!(mem.allocate_raw::<Number>(isize::MAX).is_err());
+        assert!(mem.allocate_bytes(usize::MAX).is_err());
+        assert!(mem.allocate_bytes(40).is_err());
+        // the failed allocations did not consume any memory
+        assert!(mem.allocate::<Pair>(1).is_ok());
+    }
+
+    fn cons<'t>(mem: &'t Memory<'t>, car: MemPtr<'t>, cdr: MemPtr<'t>) -> MemPtr<'t> {
+        let pair = mem.allocate::<Pair>(0).unwrap();
+        pair.modify::<Pair>(|pai| {
+            pai.car = car;
+            pai.cdr = cdr;
+        });
+        pair
+    }
+
+    fn number<'t>(mem: &'t Memory<'t>, n: u64) -> MemPtr<'t> {
+        let ptr = mem.allocate_raw::<Number>(0).unwrap();
+        ptr.modify::<Number>(|nv| nv.n = n);
+        ptr
+    }
+
+    #[test]
+    fn test_collect_compacts_live_chunks() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        number(&mem, 1);
+        let mut list = MemPtr::null();
+        for i in 0..3 {
+            cons(&mem, MemPtr::null(), MemPtr::null());
+            list = cons(&mem, number(&mem, i), list);
+        }
+        assert!(mem.used() == 2 + 3 * (3 + 2 + 3));
+
+        let before = list.clone();
+        mem.collect(&mut list);
+        assert!(mem.used() == 3 * (2 + 3));
+        assert!(list != before);
+
+        let mut expected = 3;
+        let mut current = list.clone();
+        while !current.is_null() {
+            expected -= 1;
+            let pai = current.cast::<Pair>().unwrap();
+            assert!(pai.car.cast::<Number>().unwrap().n == expected);
+            current = pai.cdr.clone();
+        }
+        assert!(expected == 0);
+    }
+
+    #[test]
+    fn test_collect_everything() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        cons(&mem, number(&mem, 1), <MemPtr>::fixnum(2).unwrap());
+        mem.collect(&mut ());
+        assert!(mem.used() == 0);
+        // the reclaimed cells can be allocated again
+        for _ in 0..20 {
+            number(&mem, 3);
+        }
+        assert!(mem.used() == 40);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "reclaimed chunk")]
+    fn test_collect_poisons_reclaimed_chunks() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let pai = cons(&mem, MemPtr::null(), MemPtr::null());
+        mem.collect(&mut ());
+        pai.cast::<Pair>().ok();
+    }
+
+    #[test]
+    fn test_collect_resets_header_bits() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        number(&mem, 1);
+        let mut pair = cons(&mem, number(&mem, 2), MemPtr::null());
+        for age in 1..=20 {
+            mem.collect(&mut pair);
+            let hdr = pair.header().unwrap();
+            assert!(!hdr.marked() && !hdr.forwarded() && !hdr.remembered());
+            assert!(pair.age().unwrap() == age.min(MAX_AGE));
+            assert!(hdr.tag() == Pair::tag() && hdr.size() == 2);
+        }
+    }
+
+    #[test]
+    fn test_gc_hooks() {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let log = events.clone();
+        mem.on_gc(move |event| log.lock().unwrap().push(event));
+        let mut pair = cons(&mem, number(&mem, 1), MemPtr::null());
+        number(&mem, 2);
+        mem.collect(&mut pair);
+        mem.collect_minor(&mut ());
+        assert!(*events.lock().unwrap() == [
+            GcEvent::Started { minor: false, used: 7 },
+            GcEvent::Marked { live: 5 },
+            GcEvent::Swept { reclaimed: 2, used: 5 },
+            GcEvent::Started { minor: true, used: 5 },
+            GcEvent::Marked { live: 0 },
+            GcEvent::Swept { reclaimed: 0, used: 5 },
+        ]);
+
+        // hooks can register hooks while they run
+        let mem: &'static Memory = Box::leak(Box::new(Memory::new(Box::leak(Box::new([0u64 ; 100])))));
+        let log = events.clone();
+        let mut registered = false;
+        mem.on_gc(move |_| if !core::mem::replace(&mut registered, true) {
+            let log = log.clone();
+            mem.on_gc(move |event| log.lock().unwrap().push(event));
+        });
+        events.lock().unwrap().clear();
+        mem.collect(&mut ());
+        assert!(events.lock().unwrap().len() == 2);
+    }
+
+    #[test]
+    fn test_gc_trigger() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut kept = cons(&mem, MemPtr::null(), MemPtr::null());
+        let mut collections = 0;
+        for n in 0..100 {
+            number(&mem, n);
+            if mem.collect_if_needed(&mut kept) {
+                collections += 1;
+                assert!(mem.used() == 3);
+            }
+        }
+        // by default, a collection is needed once 75 of the 100 cells are used
+        assert!(collections == 2);
+
+        mem.set_gc_trigger(GcTrigger::Allocated(4 * 8));
+        mem.collect(&mut ());
+        number(&mem, 1);
+        assert!(!mem.should_collect());
+        number(&mem, 2);
+        assert!(mem.collect_if_needed(&mut ()));
+        assert!(mem.used() == 0);
+    }
+
+    #[test]
+    fn test_arena() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let before = cons(&mem, MemPtr::null(), MemPtr::null());
+        let result = mem.with_arena(|arena| {
+            for n in 0..20 {
+                number(arena, n);
+            }
+            before.modify::<Pair>(|pai| pai.car = number(arena, 20));
+            cons(arena, number(arena, 21), MemPtr::null())
+        });
+        // the pair from before, the number it points to and the result
+        assert!(mem.used() == 3 + 2 + 3 + 2);
+        assert!(before.cast::<Pair>().unwrap().car.cast::<Number>().unwrap().n == 20);
+        assert!(result.cast::<Pair>().unwrap().car.cast::<Number>().unwrap().n == 21);
+        // arena survivors are still young
+        assert!(result.generation().unwrap() == Generation::Young);
+
+        // nothing is freed if the memory is collected in the meantime
+        let mut kept = mem.with_arena(|arena| {
+            number(arena, 1);
+            arena.collect(&mut ());
+            number(arena, 2);
+            number(arena, 3)
+        });
+        assert!(mem.used() == 4);
+        assert!(kept.cast::<Number>().unwrap().n == 3);
+        mem.collect(&mut kept);
+        assert!(mem.used() == 2);
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut prelude = (cons(&mem, number(&mem, 1), MemPtr::null()), mem.allocate_weak_table(1).unwrap());
+        number(&mem, 2);
+        mem.collect(&mut prelude);
+        mem.freeze();
+        let frozen = mem.used();
+        let (list, table) = prelude;
+        let hash = list.identity_hash();
+        assert!(list.is_frozen() && table.is_frozen());
+        assert!(list.cast_mut::<Pair>().is_err());
+        assert!(WeakTable::insert(&table, list.clone(), MemPtr::null()).is_err());
+
+        // frozen chunks are neither moved nor freed, even when unreachable
+        let mut young = cons(&mem, list.clone(), MemPtr::null());
+        number(&mem, 3);
+        mem.collect(&mut young);
+        assert!(mem.used() == frozen + 3 && !young.is_frozen());
+        assert!(young.cast::<Pair>().unwrap().car == list);
+        mem.collect_minor(&mut ());
+        mem.collect(&mut ());
+        assert!(mem.used() == frozen);
+        assert!(list.cast::<Pair>().unwrap().car.cast::<Number>().unwrap().n == 1);
+        assert!(list.identity_hash() == hash);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut data: [u64 ; 10] = [ 0 ; 10 ];
+        let mut mem = Memory::new(&mut data);
+        for n in 0..3 {
+            cons(&mem, number(&mem, n), MemPtr::null());
+            assert!(mem.used() == 5);
+            assert!(mem.allocate::<Pair>(3).is_err());
+            mem.reset();
+            assert!(mem.used() == 0);
+        }
+    }
+
+    #[test]
+    fn test_collect_minor() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut old = cons(&mem, number(&mem, 1), MemPtr::null());
+        cons(&mem, MemPtr::null(), MemPtr::null());
+        mem.collect(&mut old);
+        assert!(mem.used() == 5);
+
+        // young garbage is reclaimed, the old pair survives without being rooted
+        number(&mem, 9);
+        let young = number(&mem, 2);
+        assert!(young.generation().unwrap() == Generation::Young);
+        assert!(old.generation().unwrap() == Generation::Old);
+        old.modify::<Pair>(|pai| pai.cdr = young);
+        cons(&mem, MemPtr::null(), MemPtr::null());
+        mem.collect_minor(&mut ());
+        assert!(mem.used() == 7);
+        // the pointer from the old pair was updated to the moved number
+        let cdr = &old.cast::<Pair>().unwrap().cdr;
+        assert!(cdr.cast::<Number>().unwrap().n == 2);
+        assert!(cdr.generation().unwrap() == Generation::Old && cdr.age().unwrap() == 1);
+        // old chunks do not age in minor collections
+        assert!(old.age().unwrap() == 1);
+
+        // the survivors were promoted, so a minor collection keeps everything
+        mem.collect_minor(&mut ());
+        assert!(mem.used() == 7);
+        mem.collect(&mut ());
+        assert!(mem.used() == 0);
+    }
+
+    #[test]
+    fn test_collect_cycles_and_immediates() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        cons(&mem, MemPtr::null(), MemPtr::null());
+        let mut cycle = cons(&mem, <MemPtr>::fixnum(-5).unwrap(), MemPtr::null());
+        cycle.modify::<Pair>(|pai| pai.cdr = cycle.clone());
+        let mut fixnum = <MemPtr>::fixnum(9).unwrap();
+        let mut roots = [&mut cycle, &mut fixnum];
+        mem.collect(&mut roots);
+
+        assert!(mem.used() == 3);
+        assert!(fixnum.as_fixnum() == Some(9));
+        let pai = cycle.cast::<Pair>().unwrap();
+        assert!(pai.car.as_fixnum() == Some(-5));
+        assert!(pai.cdr == cycle);
+    }
+
+    #[test]
+    fn test_collect_does_not_trace_raw_chunks() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let garbage = number(&mem, 1);
+        // a raw chunk whose contents happen to look like a pointer
+        let mut raw = mem.allocate_raw::<Pair>(0).unwrap();
+        raw.modify::<Pair>(|pai| {
+            pai.car = garbage.clone();
+            pai.cdr = MemPtr::null();
+        });
+        mem.collect(&mut raw);
+        assert!(mem.used() == 3);
+        assert!(raw.cast::<Pair>().unwrap().car == garbage);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_conservative_roots() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        mem.with_conservative_roots(|| {
+            let pair = cons(&mem, number(&mem, 7), MemPtr::null());
+            let before = pair.clone();
+            cons(&mem, MemPtr::null(), MemPtr::null());
+            mem.collect(&mut ());
+            // found on the stack, so kept alive without being moved
+            assert!(pair == before);
+            assert!(pair.cast::<Pair>().unwrap().car.cast::<Number>().unwrap().n == 7);
+            // nested scopes keep scanning from the outermost one
+            mem.with_conservative_roots(|| mem.collect(&mut ()));
+            assert!(pair.cast::<Pair>().unwrap().car.cast::<Number>().unwrap().n == 7);
+            // the heap can still be walked and allocated from
+            assert!(mem.chunks(mem.free_pointer.load(Ordering::Acquire)).count() >= 2);
+            number(&mem, 8);
+        });
+        // outside of the scope the stack is not scanned anymore
+        mem.collect(&mut ());
+        assert!(mem.used() == 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_collect_during_conservative_roots() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let scanning = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                while !scanning.load(Ordering::Acquire) {
+                    std::thread::yield_now();
+                }
+                // waits for the other thread to stop scanning
+                mem.collect(&mut ());
+            });
+            mem.with_conservative_roots(|| {
+                let n = number(&mem, 7);
+                scanning.store(true, Ordering::Release);
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                number(&mem, 8);
+                assert!(mem.collections.load(Ordering::Acquire) == 0);
+                assert!(n.cast::<Number>().unwrap().n == 7);
+            });
+        });
+        assert!(mem.collections.load(Ordering::Acquire) == 1 && mem.used() == 0);
+    }
+
+    #[test]
+    fn test_pinned_chunks_leave_holes() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        number(&mem, 1);
+        let pinned = number(&mem, 2);
+        number(&mem, 3);
+        let mut moved = number(&mem, 4);
+        mem.collect_pinned(&mut moved, &[pinned.ptr as *mut u64]);
+
+        // the first number is replaced by a filler, the fourth slides into the third
+        assert!(mem.used() == 6);
+        let top = mem.free_pointer.load(Ordering::Acquire);
+        let tags: Vec<usize> = mem.chunks(top).map(|(_, hdr)| hdr.tag()).collect();
+        assert!(tags == [Filler::tag(), Number::tag(), Number::tag()]);
+        assert!(pinned.cast::<Number>().unwrap().n == 2);
+        let live: Vec<(usize, MemPtr)> = mem.iter_chunks().collect();
+        assert!(live == [(Number::tag(), pinned.clone()), (Number::tag(), moved.clone())]);
+        assert!(moved.cast::<Number>().unwrap().n == 4);
+
+        let report = mem.fragmentation();
+        assert!(report.holes == [2] && report.largest_allocatable == 93);
+        assert!(report.wasted == 100.0 * 2.0 / 6.0);
+
+        // once unpinned, the hole is closed
+        mem.collect(&mut moved);
+        assert!(mem.used() == 2);
+        assert!(mem.fragmentation().holes.is_empty() && mem.fragmentation().wasted == 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_shadow_stack_roots() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut other_data: [u64 ; 10] = [ 0 ; 10 ];
+        let other = Memory::new(&mut other_data);
+        let mut elsewhere = number(&other, 3);
+        root!(other, elsewhere);
+
+        number(&mem, 1);
+        let mut list = cons(&mem, number(&mem, 2), MemPtr::null());
+        root!(mem, list);
+        {
+            let mut first = number(&mem, 5);
+            let mut second = number(&mem, 6);
+            root!(mem, first, second);
+            mem.collect(&mut ());
+            assert!(mem.used() == 3 + 2 + 2 + 2);
+            assert!(first.cast::<Number>().unwrap().n == 5);
+            assert!(second.cast::<Number>().unwrap().n == 6);
+        }
+        // the inner roots went out of scope
+        mem.collect(&mut ());
+        assert!(mem.used() == 3 + 2);
+        let pai = list.cast::<Pair>().unwrap();
+        assert!(pai.car.cast::<Number>().unwrap().n == 2);
+
+        // roots of another memory are left alone
+        assert!(other.used() == 2);
+        assert!(elsewhere.cast::<Number>().unwrap().n == 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    #[should_panic(expected = "roots must be dropped in reverse order")]
+    fn test_shadow_stack_roots_out_of_order() {
+        let mut data: [u64 ; 10] = [ 0 ; 10 ];
+        let mem = Memory::new(&mut data);
+        let (mut first, mut second) = (number(&mem, 1), number(&mem, 2));
+        let (first, _second) = unsafe { (Root::new(&mem, &mut first), Root::new(&mem, &mut second)) };
+        drop(first);
+    }
+
+    #[test]
+    fn test_ephemerons() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let mut key = number(&mem, 1);
+        // the value refers back to its key, which must not keep the key alive
+        let value = cons(&mem, key.clone(), MemPtr::null());
+        let mut alive = mem.allocate_ephemeron(key.clone(), value).unwrap();
+        let dead_key = number(&mem, 2);
+        let mut dead = mem.allocate_ephemeron(dead_key.clone(), number(&mem, 3)).unwrap();
+        // an ephemeron whose key only becomes reachable through another ephemeron
+        let mut chained = mem.allocate_ephemeron(alive.clone(), number(&mem, 4)).unwrap();
+
+        mem.collect(&mut (&mut key, &mut alive, &mut dead, &mut chained));
+
+        let eph = alive.cast::<Ephemeron>().unwrap();
+        assert!(!eph.is_broken());
+        assert!(*eph.key() == key);
+        assert!(eph.value().cast::<Pair>().unwrap().car == key);
+        let eph = dead.cast::<Ephemeron>().unwrap();
+        assert!(eph.is_broken() && eph.value().is_null());
+        let eph = chained.cast::<Ephemeron>().unwrap();
+        assert!(*eph.key() == alive);
+        assert!(eph.value().cast::<Number>().unwrap().n == 4);
+        // key, pair, three ephemerons and the chained value survive
+        assert!(mem.used() == 2 + 3 + 3 * 3 + 2);
+
+        // dropping the key breaks the ephemeron and frees its value
+        mem.collect(&mut (&mut alive, &mut chained));
+        assert!(alive.cast::<Ephemeron>().unwrap().is_broken());
+        assert!(mem.used() == 2 * 3 + 2);
+    }
+
+    #[test]
+    fn test_weak_table() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let mut table = mem.allocate_weak_table(4).unwrap();
+        let mut keys: Vec<MemPtr> = (0..4).map(|i| number(&mem, i)).collect();
+        for (i, key) in keys.iter().enumerate() {
+            // values refer to their keys, which must not keep them alive
+            let value = cons(&mem, key.clone(), <MemPtr>::fixnum(i as i64).unwrap());
+            WeakTable::insert(&table, key.clone(), value).unwrap();
+        }
+        assert!(WeakTable::insert(&table, number(&mem, 5), MemPtr::null()).is_err());
+        assert!(WeakTable::insert(&table, MemPtr::null(), MemPtr::null()).is_err());
+        WeakTable::insert(&table, keys[3].clone(), <MemPtr>::fixnum(33).unwrap()).unwrap();
+        assert!(WeakTable::remove(&table, &keys[3]).unwrap().unwrap().as_fixnum() == Some(33));
+        assert!(WeakTable::get(&table, &keys[3]).unwrap().is_none());
+        assert!(table.cast::<WeakTable>().unwrap().len() == 3);
+
+        // only the even keys stay reachable
+        keys.remove(3);
+        keys.remove(1);
+        mem.collect(&mut (&mut table, &mut keys));
+        assert!(table.cast::<WeakTable>().unwrap().len() == 2);
+        for (key, i) in keys.iter().zip([0, 2]) {
+            let value = WeakTable::get(&table, key).unwrap().unwrap();
+            let pai = value.cast::<Pair>().unwrap();
+            assert!(pai.car == *key);
+            assert!(pai.cdr.as_fixnum() == Some(i));
+        }
+        // table, two keys and their values
+        assert!(mem.used() == 1 + 1 + 4 * 4 * 2 + 2 * (2 + 3));
+
+        keys.clear();
+        mem.collect(&mut table);
+        assert!(table.cast::<WeakTable>().unwrap().is_empty());
+        assert!(mem.used() == 1 + 1 + 4 * 4 * 2);
+    }
+
+    #[test]
+    fn test_memory_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Memory<'static>>();
+        assert_send_sync::<Memory<'static, Vec<u64>>>();
+    }
+
+    fn check_pair<B: Backing>(mem: &Memory<'_, B>) {
+        let n = mem.allocate_raw::<Number>(0).unwrap();
+        n.modify::<Number>(|nv| nv.n = 42);
+        let ptr = mem.allocate::<Pair>(0).unwrap();
+        ptr.modify::<Pair>(|pai| {
+            pai.car = n.clone();
+            pai.cdr = n.clone();
+        });
+        let pai = ptr.cast::<Pair>().unwrap();
+        assert!(pai.cdr.cast::<Number>().unwrap().n == 42);
+    }
+
+    #[test]
+    fn test_owned_backing() {
+        check_pair(&Memory::with_backing(vec![0 ; 100]));
+        check_pair(&Memory::with_backing(vec![0 ; 100].into_boxed_slice()));
+    }
+
+    #[test]
+    fn test_static_backing() {
+        let data: &'static mut [u64 ; 100] = Box::leak(Box::new([0 ; 100]));
+        check_pair(&Memory::with_backing(data));
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn test_mmap_backing() {
+        let mut map = Mmap::new(100).unwrap();
+        assert!(map.cells().len() >= 100);
+        assert!(map.cells().iter().all(|&c| c == 0));
+        check_pair(&Memory::with_backing(map));
+    }
+
+    #[test]
+    fn test_concurrent_allocation() {
+        let mut data: [u64 ; 1000] = [ 0 ; 1000 ];
+        let mem = Memory::new(&mut data);
+        let chunks: Vec<Vec<usize>> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..4).map(|i| {
+                let mem = &mem;
+                s.spawn(move || {
+                    (0..50).map(|_| {
+                        let n = mem.allocate_raw::<Number>(0).unwrap();
+                        n.modify::<Number>(|nv| nv.n = i);
+                        n.ptr as usize
+                    }).collect()
+                })
+            }).collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+
+        // every allocation received its own chunk
+        let mut all: Vec<usize> = chunks.iter().flatten().copied().collect();
+        all.sort();
+        all.dedup();
+        assert!(all.len() == 200);
+        for (i, worker) in chunks.iter().enumerate() {
+            for &addr in worker {
+                let n: MemPtr = MemPtr { ptr: addr as *const u64, pd: PhantomData };
+                assert!(n.cast::<Number>().unwrap().n == i as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_collect_during_concurrent_allocation() {
+        let mut data = vec![0u64 ; 10000];
+        let mem = Memory::new(&mut data);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                let mem = &mem;
+                s.spawn(move || {
+                    // the chunks are unrooted, so a collection may move or
+                    // free them as soon as the allocation leaves the gate
+                    for _ in 0..1000 {
+                        mem.allocate_raw::<Number>(0).unwrap();
+                    }
+                });
+            }
+            for _ in 0..20 {
+                mem.collect(&mut ());
+                // every chunk still starts with the header its allocation wrote
+                let _closed = mem.gate.close();
+                let top = mem.free_pointer.load(Ordering::Acquire);
+                assert!(mem.chunks(top).all(|(_, hdr)| hdr.tag() == Number::tag()));
+            }
+        });
+        mem.collect(&mut ());
+        assert!(mem.used() == 0);
+    }
 }
@@ -0,0 +1,44 @@
+use alloc::{boxed::Box, vec::Vec};
+
+/// A region of cells that can serve as the backing storage of a `Memory`.
+///
+/// # Safety
+///
+/// `cells` must return the same region every time it is called, and 
+/// moving the backing itself must not move that region. The `Memory` 
+/// holds on to raw pointers into it for as long as the backing lives.
+pub unsafe trait Backing {
+    /// The cells available to the allocator
+    fn cells(&mut self) -> &mut [u64];
+}
+
+// SAFETY: the borrowed slice is never moved, only the reference is.
+unsafe impl Backing for &mut [u64] {
+    fn cells(&mut self) -> &mut [u64] {
+        self
+    }
+}
+
+/// Statically allocated arrays, typically a `&'static mut [u64 ; N]` 
+/// on targets without a heap.
+// SAFETY: the borrowed array is never moved, only the reference is.
+unsafe impl<const N: usize> Backing for &mut [u64; N] {
+    fn cells(&mut self) -> &mut [u64] {
+        &mut self[..]
+    }
+}
+
+// SAFETY: the buffer of a vector is not moved when the vector is, 
+// and the vector cannot be resized through `cells`.
+unsafe impl Backing for Vec<u64> {
+    fn cells(&mut self) -> &mut [u64] {
+        self
+    }
+}
+
+// SAFETY: same as for `Vec<u64>`.
+unsafe impl Backing for Box<[u64]> {
+    fn cells(&mut self) -> &mut [u64] {
+        self
+    }
+}
@@ -0,0 +1,94 @@
+use core::{marker::PhantomData, ops::Deref, ptr::without_provenance};
+
+use super::{Any, MemPtr, Trace};
+
+/// Pointer value of an empty cell. It is neither a fixnum nor aligned
+/// to a cell, so it is never mistaken for a value by the collector, and
+/// it is the one special value that `MemPtr::special` does not encode.
+const EMPTY: usize = 0b010;
+
+/// A slot that holds a pointer, or nothing at all, such as a variable
+/// that is declared but not yet initialized. Unlike the null pointer,
+/// which is a value of its own (the empty list), an empty cell has no
+/// value, and dereferencing it panics.
+#[repr(transparent)]
+pub struct Cell<'t, T = Any> {
+    ptr: MemPtr<'t, T>
+}
+
+impl<'t, T> Cell<'t, T> {
+    /// Returns a cell without a value
+    pub fn empty() -> Cell<'t, T> {
+        Cell { ptr: MemPtr { ptr: without_provenance(EMPTY), pd: PhantomData } }
+    }
+
+    /// Returns a cell holding the given pointer
+    pub fn new(ptr: MemPtr<'t, T>) -> Cell<'t, T> {
+        Cell { ptr }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ptr.ptr.addr() == EMPTY
+    }
+
+    /// Returns the pointer in the cell, if any
+    pub fn get(&self) -> Option<&MemPtr<'t, T>> {
+        (!self.is_empty()).then_some(&self.ptr)
+    }
+
+    /// Stores the pointer in the cell, replacing its previous value
+    pub fn set(&mut self, ptr: MemPtr<'t, T>) {
+        self.ptr = ptr;
+    }
+
+    /// Takes the pointer out of the cell, leaving it empty
+    pub fn take(&mut self) -> Option<MemPtr<'t, T>> {
+        let cell = core::mem::take(self);
+        (!cell.is_empty()).then_some(cell.ptr)
+    }
+}
+
+impl<'t, T> Deref for Cell<'t, T> {
+    type Target = MemPtr<'t, T>;
+
+    fn deref(&self) -> &MemPtr<'t, T> {
+        self.get().expect("dereferencing an empty cell")
+    }
+}
+
+impl<T> Default for Cell<'_, T> {
+    fn default() -> Self {
+        Cell::empty()
+    }
+}
+
+impl<T> core::fmt::Debug for Cell<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.get() {
+            Some(ptr) => f.debug_tuple("Cell").field(ptr).finish(),
+            None => f.write_str("Cell(<empty>)")
+        }
+    }
+}
+
+impl<T> Clone for Cell<'_, T> {
+    fn clone(&self) -> Self {
+        Cell { ptr: self.ptr.clone() }
+    }
+}
+
+impl<T> PartialEq for Cell<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T> Eq for Cell<'_, T> {}
+
+impl<T> Trace for Cell<'_, T> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        if !self.is_empty() {
+            self.ptr.trace(visitor)
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::{hint::black_box, sync::atomic::Ordering, thread::{self, ThreadId}};
+
+use super::{sync::Closed, Backing, Memory};
+
+/// Stack scanning state of a memory
+pub(super) type StackBase = Option<(usize, ThreadId)>;
+
+/// One bit for every cell of linear
+struct Bitmap(Vec<u64>);
+
+impl Bitmap {
+    fn new(cells: usize) -> Bitmap {
+        Bitmap(vec![0; cells.div_ceil(64)])
+    }
+
+    fn get(&self, offset: usize) -> bool {
+        self.0[offset / 64] & 1 << (offset % 64) != 0
+    }
+
+    fn set(&mut self, offset: usize) {
+        self.0[offset / 64] |= 1 << (offset % 64)
+    }
+}
+
+/// Number of registers that can be spilled by `spill_registers`
+const REGISTERS: usize = 10;
+
+/// Copies the callee-saved registers, which may hold pointers 
+/// of the callers that are nowhere on the stack, to the stack.
+#[inline(always)]
+fn spill_registers() -> [usize; REGISTERS] {
+    #[allow(unused_mut)]
+    let mut registers = [0usize; REGISTERS];
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        // SAFETY: only writes within `registers`
+        std::arch::asm!(
+            "mov [{0}], rbx",
+            "mov [{0} + 8], rbp",
+            "mov [{0} + 16], r12",
+            "mov [{0} + 24], r13",
+            "mov [{0} + 32], r14",
+            "mov [{0} + 40], r15",
+            in(reg) registers.as_mut_ptr(),
+            options(nostack, preserves_flags)
+        );
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        // SAFETY: only writes within `registers`
+        std::arch::asm!(
+            "stp x19, x20, [{0}]",
+            "stp x21, x22, [{0}, #16]",
+            "stp x23, x24, [{0}, #32]",
+            "stp x25, x26, [{0}, #48]",
+            "stp x27, x28, [{0}, #64]",
+            in(reg) registers.as_mut_ptr(),
+            options(nostack, preserves_flags)
+        );
+    }
+    registers
+}
+
+/// Calls `f` in a frame of its own, so that its locals are always
+/// below the base taken by `with_conservative_roots`, even when `f`
+/// would otherwise be inlined into the frame of the base.
+#[inline(never)]
+fn trampoline<R>(f: impl FnOnce() -> R) -> R {
+    black_box(f)()
+}
+
+/// Disables stack scanning again when the outermost 
+/// `with_conservative_roots` returns or unwinds.
+struct Disable<'m, 't, B: Backing>(&'m Memory<'t, B>);
+
+impl<B: Backing> Drop for Disable<'_, '_, B> {
+    fn drop(&mut self) {
+        *self.0.stack_base.lock().unwrap() = None;
+    }
+}
+
+impl<B: Backing> Memory<'_, B> {
+    /// Runs `f` with conservative stack scanning enabled.
+    ///
+    /// Every collection on this thread during `f` considers each word on 
+    /// the stack (between this call and the collection) and in the 
+    /// callee-saved registers that looks like a pointer to a chunk to be
+    /// a root, so pointers held in local variables do not have to be passed
+    /// as roots explicitly. 
+    ///
+    /// Chunks found this way cannot be moved, since the words pointing to 
+    /// them may not be pointers at all, so the cells in front of them can 
+    /// only be reused after they are unreachable. Collections on other
+    /// threads wait until `f` returns.
+    #[inline(never)]
+    pub fn with_conservative_roots<R>(&self, f: impl FnOnce() -> R) -> R {
+        let marker = 0u8;
+        let base = black_box(&marker) as *const u8 as usize;
+        let _disable = {
+            // not while a collection runs, see `close_for_collection`
+            let _entered = self.gate.enter();
+            let mut stack_base = self.stack_base.lock().unwrap();
+            match *stack_base {
+                // nested call on the same thread, the outer base covers this frame
+                Some((_, thread)) if thread == thread::current().id() => None,
+                Some(_) => panic!("conservative stack scanning is enabled for another thread"),
+                None => {
+                    *stack_base = Some((base, thread::current().id()));
+                    Some(Disable(self))
+                }
+            }
+        };
+        trampoline(f)
+    }
+
+    /// Closes the gate for a collection once no other thread scans its
+    /// stack, whose pointers the collection cannot find. Scanning is only
+    /// enabled from inside the gate, so it stays off while the gate is closed.
+    pub(super) fn close_for_collection(&self) -> Closed<'_> {
+        loop {
+            let closed = self.gate.close();
+            match *self.stack_base.lock().unwrap() {
+                Some((_, thread)) if thread != thread::current().id() => {},
+                _ => return closed
+            }
+            // lets the other thread allocate until it is done scanning
+            drop(closed);
+            thread::yield_now();
+        }
+    }
+
+    /// Returns the chunks that are pointed to by words on the stack, 
+    /// sorted by address, if stack scanning is enabled.
+    #[inline(never)]
+    pub(super) fn stack_roots(&self, top: *mut u64) -> Vec<*mut u64> {
+        let Some((base, thread)) = *self.stack_base.lock().unwrap() else {
+            return Vec::new();
+        };
+        assert!(thread == thread::current().id(), "conservative stack scanning is enabled for another thread");
+        let registers = black_box(spill_registers());
+
+        let mut starts = Bitmap::new(self.offset(top));
+        self.chunks(top).for_each(|(chunk, _)| starts.set(self.offset(chunk)));
+        let mut roots = Vec::new();
+        let mut consider = |word: usize| {
+            let chunk = word as *mut u64;
+            if word.is_multiple_of(size_of::<u64>()) && chunk >= self.start && chunk < top && starts.get(self.offset(chunk)) {
+                roots.push(chunk);
+            }
+        };
+
+        registers.iter().for_each(|&word| consider(word));
+        let marker = 0usize;
+        let mut addr = black_box(&marker) as *const usize as usize;
+        while addr < base {
+            unsafe {
+                // SAFETY: all words between the current frame and the frame of
+                // `with_conservative_roots` are part of this thread's stack.
+                consider(std::ptr::read_volatile(addr as *const usize));
+            }
+            addr += size_of::<usize>();
+        }
+        // the free pointer is only read to make sure it did not move
+        debug_assert!(self.free_pointer.load(Ordering::Relaxed) == top);
+        roots.sort();
+        roots.dedup();
+        roots
+    }
+}
@@ -0,0 +1,559 @@
+use core::sync::atomic::Ordering;
+
+use alloc::{boxed::Box, vec::Vec};
+
+#[cfg(feature = "std")]
+use super::shadow::ShadowRoots;
+use super::{weak, Backing, ChunkContent, Ephemeron, Filler, Header, Memory, MemPtr, Trace, WeakTable, MAX_AGE, POISON};
+
+/// Iterator over the chunks laid out between two addresses of linear
+struct Chunks {
+    current: *mut u64,
+    top: *mut u64
+}
+
+impl Iterator for Chunks {
+    type Item = (*mut u64, Header);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current >= self.top {
+            return None;
+        }
+        unsafe {
+            // SAFETY: every chunk below the free pointer starts with an
+            // initialized header, and the next chunk starts right after it.
+            let chunk = self.current;
+            let hdr = *(chunk as *const Header);
+            self.current = chunk.add(hdr.cells());
+            Some((chunk, hdr))
+        }
+    }
+}
+
+/// Progress of a collection, passed to the hooks registered with `Memory::on_gc`.
+/// Sizes are in cells, including the headers of the chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcEvent {
+    /// Before anything is collected
+    Started { minor: bool, used: usize },
+    /// The reachable chunks have been marked, but none of them moved yet
+    Marked { live: usize },
+    /// The live chunks have been compacted, pointers to reclaimed chunks are dangling
+    Swept { reclaimed: usize, used: usize }
+}
+
+/// When `Memory::collect_if_needed` decides to collect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcTrigger {
+    /// Once this many bytes were allocated since the previous collection,
+    /// which bounds the pause of a collection
+    Allocated(usize),
+    /// Once this percentage of the memory is in use (or of its quota, 
+    /// if smaller), which makes collections as rare as possible
+    Occupancy(u8)
+}
+
+impl Default for GcTrigger {
+    fn default() -> GcTrigger {
+        GcTrigger::Occupancy(75)
+    }
+}
+
+/// A callback registered with `Memory::on_gc`
+pub(super) type Hook = Box<dyn FnMut(GcEvent) + Send>;
+
+/// Forwarding addresses of the chunks that move, sorted by their old address
+struct Forwarding(Vec<(*mut u64, *mut u64)>);
+
+impl Forwarding {
+    fn lookup(&self, old: *const u64) -> *mut u64 {
+        let i = self.0.binary_search_by_key(&old, |(from, _)| *from as *const u64)
+            .expect("pointer to a chunk that was not forwarded");
+        self.0[i].1
+    }
+}
+
+/// The part of linear being collected. The chunks below `from` are
+/// neither moved nor traced, except for the remembered ones.
+#[derive(Clone, Copy)]
+struct Region {
+    from: *mut u64,
+    top: *mut u64
+}
+
+impl Region {
+    /// Returns true if `ptr` points to a chunk in the region
+    fn contains(&self, ptr: &MemPtr<'_>) -> bool {
+        let addr = ptr.ptr as *mut u64;
+        !ptr.is_fixnum() && addr >= self.from && addr < self.top
+    }
+
+    /// Iterates over the chunks in the region
+    fn chunks(&self) -> Chunks {
+        Chunks { current: self.from, top: self.top }
+    }
+}
+
+impl<'t, B: Backing> Memory<'t, B> {
+    /// Calls the visitor with every pointer in the given chunk
+    ///
+    /// # Safety
+    ///
+    /// `chunk` must point to an initialized chunk that is not raw.
+    unsafe fn trace_chunk(&self, chunk: *mut u64, hdr: Header, visitor: &mut dyn FnMut(&mut MemPtr<'_>)) {
+        let tracer = self.tracers[hdr.tag()].get()
+            .expect("no tracer registered for the tag of a traced chunk");
+        tracer(chunk, visitor)
+    }
+
+    /// Calls the finalizer registered for the tag of the chunk, if any
+    ///
+    /// # Safety
+    ///
+    /// `chunk` must point to an initialized chunk that is never used again.
+    pub(super) unsafe fn finalize_chunk(&self, chunk: *mut u64, hdr: Header) {
+        if let Some(finalizer) = self.finalizers[hdr.tag()].get() {
+            finalizer(chunk)
+        }
+    }
+
+    /// Finalizes the chunks in the region that were not marked,
+    /// before they are overwritten
+    fn finalize(&self, region: Region) {
+        if !self.finalizing.load(Ordering::Acquire) {
+            return;
+        }
+        for (chunk, hdr) in region.chunks().filter(|(_, hdr)| !hdr.marked()) {
+            unsafe {
+                // SAFETY: the chunk is unreachable, so it is freed
+                self.finalize_chunk(chunk, hdr);
+            }
+        }
+    }
+
+    /// Returns the offset of `chunk` from the start of linear in cells
+    pub(super) fn offset(&self, chunk: *const u64) -> usize {
+        (chunk as usize - self.start as usize) / size_of::<u64>()
+    }
+
+    /// Iterates over the chunks below the given free pointer
+    pub(super) fn chunks(&self, top: *mut u64) -> impl Iterator<Item = (*mut u64, Header)> {
+        Chunks { current: self.start, top }
+    }
+
+    /// Returns the traced chunks below the collected region that were
+    /// modified since the previous collection, as only those can point
+    /// into the region.
+    fn remembered(&self, region: Region) -> Vec<*mut u64> {
+        // frozen chunks cannot be modified
+        Chunks { current: self.frozen_top.load(Ordering::Acquire), top: region.from }
+            .filter(|(_, hdr)| hdr.remembered() && !hdr.is_raw())
+            .map(|(chunk, _)| chunk)
+            .collect()
+    }
+
+    /// Sets the mark bit of every chunk in the region reachable from the roots,
+    /// the remembered chunks and the pinned chunks, returns the number of 
+    /// cells they take up.
+    ///
+    /// The value of an ephemeron or weak table entry is only marked once its 
+    /// key is marked, the ones whose key remains unmarked are cleared.
+    fn mark(&self, roots: &mut impl Trace, remembered: &[*mut u64], pinned: &[*mut u64], region: Region) -> usize {
+        let mut live = 0;
+        let mut pending: Vec<*mut u64> = pinned.to_vec();
+        // the key and value of every ephemeron and weak table entry
+        let mut ephemerons: Vec<(*mut MemPtr<'static>, *mut MemPtr<'static>)> = Vec::new();
+        roots.trace(&mut |ptr| pending.push(ptr.ptr as *mut u64));
+        for &chunk in remembered {
+            unsafe {
+                // SAFETY: remembered chunks are in use and not raw
+                let hdr = *(chunk as *const Header);
+                self.trace_chunk(chunk, hdr, &mut |ptr| pending.push(ptr.ptr as *mut u64));
+            }
+        }
+        loop {
+            while let Some(addr) = pending.pop() {
+                let ptr = MemPtr { ptr: addr, pd: core::marker::PhantomData };
+                if !region.contains(&ptr) {
+                    continue;
+                }
+                let hdr = ptr.header().unwrap();
+                if hdr.marked() {
+                    continue;
+                }
+                unsafe {
+                    // SAFETY: the chunk is in use
+                    *(addr as *mut Header) = hdr.with_marked(true);
+                }
+                live += hdr.cells();
+                if hdr.tag() == Ephemeron::tag() {
+                    let ephemeron = addr as *mut Ephemeron<'static>;
+                    unsafe {
+                        // SAFETY: the chunk is an ephemeron in use
+                        ephemerons.push((&raw mut (*ephemeron).key, &raw mut (*ephemeron).value));
+                    }
+                } else if hdr.tag() == WeakTable::tag() {
+                    unsafe {
+                        // SAFETY: the chunk is a weak table in use
+                        weak::weak_entries(addr, &mut |key, value| ephemerons.push((key, value)));
+                    }
+                } else if !hdr.is_raw() {
+                    unsafe {
+                        // SAFETY: the chunk is in use and not raw
+                        self.trace_chunk(addr, hdr, &mut |ptr| pending.push(ptr.ptr as *mut u64));
+                    }
+                }
+            }
+            // the values of ephemerons with a reachable key are reachable as well,
+            // which may in turn make the keys of other ephemerons reachable
+            let before = ephemerons.len();
+            ephemerons.retain(|&(key, value)| unsafe {
+                // SAFETY: the ephemeron or weak table is marked, so it is in use
+                let key_alive = !region.contains(&*key) || (*key).header().unwrap().marked();
+                if key_alive {
+                    pending.push((*value).ptr as *mut u64);
+                }
+                !key_alive
+            });
+            if ephemerons.len() == before {
+                break;
+            }
+        }
+        for (key, value) in ephemerons {
+            unsafe {
+                // SAFETY: the ephemeron or weak table is marked, so it is in use
+                *key = MemPtr::null();
+                *value = MemPtr::null();
+            }
+        }
+        live
+    }
+
+    /// Computes where every marked chunk will be moved to, and sets the 
+    /// forwarded bit of the ones that do not stay in place. Pinned chunks 
+    /// stay where they are, the free cells in front of them are returned
+    /// as holes (start and length in cells).
+    fn forwarding(&self, pinned: &[*mut u64], region: Region) -> (Forwarding, Vec<(*mut u64, usize)>) {
+        let mut to = region.from;
+        let mut holes = Vec::new();
+        let mut moved = Vec::new();
+        for (chunk, hdr) in region.chunks().filter(|(_, hdr)| hdr.marked()) {
+            if pinned.binary_search(&chunk).is_ok() {
+                if to < chunk {
+                    holes.push((to, self.offset(chunk) - self.offset(to)));
+                }
+                to = chunk;
+            }
+            if to != chunk {
+                unsafe {
+                    // SAFETY: the chunk is in use
+                    *(chunk as *mut Header) = hdr.with_forwarded(true);
+                }
+                moved.push((chunk, to));
+            }
+            // only the address is computed, it stays within linear
+            to = to.wrapping_add(hdr.cells());
+        }
+        (Forwarding(moved), holes)
+    }
+
+    /// Points the roots and the pointers in remembered and live chunks to the new locations
+    fn update(&self, roots: &mut impl Trace, remembered: &[*mut u64], forwarding: &Forwarding, region: Region) {
+        let mut visitor = |ptr: &mut MemPtr<'_>| {
+            if region.contains(ptr) && ptr.header().unwrap().forwarded() {
+                ptr.ptr = forwarding.lookup(ptr.ptr);
+            }
+        };
+        roots.trace(&mut visitor);
+        let live = region.chunks()
+            .filter(|(_, hdr)| hdr.marked())
+            .map(|(chunk, _)| chunk);
+        for chunk in remembered.iter().copied().chain(live) {
+            unsafe {
+                // SAFETY: the chunk is live and has not been moved yet
+                let hdr = *(chunk as *const Header);
+                if !hdr.is_raw() {
+                    self.trace_chunk(chunk, hdr, &mut visitor);
+                }
+            }
+        }
+    }
+
+    /// Moves the forwarded chunks to their new location and resets the
+    /// collector bits of every live chunk, returns the new free pointer.
+    /// The age of the live chunks is increased if they are promoted.
+    fn slide(&self, forwarding: &Forwarding, region: Region, promote: bool) -> *mut u64 {
+        let mut top = region.from;
+        for (chunk, hdr) in region.chunks().filter(|(_, hdr)| hdr.marked()) {
+            let to = if hdr.forwarded() { forwarding.lookup(chunk) } else { chunk };
+            let cells = hdr.cells();
+            unsafe {
+                // SAFETY: chunks only move towards the start of linear and
+                // in address order, so a chunk never overwrites a live chunk
+                // (or the header of the next chunk) that still has to be moved.
+                core::ptr::copy(chunk, to, cells);
+                *(to as *mut Header) = hdr
+                    .with_marked(false)
+                    .with_forwarded(false)
+                    .with_remembered(false)
+                    .with_age(if promote { (hdr.age() + 1).min(MAX_AGE) } else { hdr.age() });
+                top = to.add(cells);
+            }
+        }
+        top
+    }
+
+    /// Rehashes the weak tables, now that their keys have been moved
+    fn rehash(&self, remembered: &[*mut u64], region: Region) {
+        let live = region.chunks().map(|(chunk, _)| chunk);
+        for chunk in remembered.iter().copied().chain(live) {
+            unsafe {
+                // SAFETY: the chunk is live
+                if (*(chunk as *const Header)).tag() == WeakTable::tag() {
+                    weak::rehash(chunk);
+                }
+            }
+        }
+    }
+
+    /// Fills the holes left in front of pinned chunks with raw filler chunks,
+    /// so that linear can still be walked chunk by chunk.
+    fn fill(&self, holes: &[(*mut u64, usize)]) {
+        for &(hole, cells) in holes {
+            unsafe {
+                // SAFETY: the hole lies within linear and is not used by any live chunk
+                *(hole as *mut Header) = Header::initialize(true, Filler::tag(), cells - 1);
+            }
+            self.poison(hole.wrapping_add(1), cells - 1);
+        }
+    }
+
+    /// Overwrites the given reclaimed cells with `POISON` in debug builds,
+    /// so that pointers to them are caught by `MemPtr::cast` instead of
+    /// silently reading stale chunks.
+    pub(super) fn poison(&self, from: *mut u64, cells: usize) {
+        if cfg!(debug_assertions) {
+            unsafe {
+                // SAFETY: the cells lie within linear and are no longer in use
+                core::slice::from_raw_parts_mut(from, cells).fill(POISON);
+            }
+        }
+    }
+
+    /// Registers a callback that is called at every stage of every collection,
+    /// after the ones registered before. The callback runs in the middle of 
+    /// the collection, it must not allocate from or collect this memory, 
+    /// which would wait forever for the collection to finish. It may register
+    /// other callbacks, which are called from the next stage on.
+    pub fn on_gc(&self, hook: impl FnMut(GcEvent) + Send + 'static) {
+        self.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    fn notify(&self, event: GcEvent) {
+        // the hooks are called outside the lock, so they can register hooks
+        let mut hooks = core::mem::take(&mut *self.hooks.lock().unwrap());
+        for hook in hooks.iter_mut() {
+            hook(event);
+        }
+        let mut registered = self.hooks.lock().unwrap();
+        hooks.append(&mut registered);
+        *registered = hooks;
+    }
+
+    /// Sets when `collect_if_needed` collects
+    pub fn set_gc_trigger(&self, trigger: GcTrigger) {
+        *self.trigger.lock().unwrap() = trigger;
+    }
+
+    /// Returns true if the trigger set with `set_gc_trigger` was reached
+    pub fn should_collect(&self) -> bool {
+        let top = self.free_pointer.load(Ordering::Acquire);
+        let used = self.offset(top) * size_of::<u64>();
+        match *self.trigger.lock().unwrap() {
+            GcTrigger::Allocated(bytes) => {
+                let old_top = self.old_top.load(Ordering::Acquire);
+                (self.offset(top) - self.offset(old_top)) * size_of::<u64>() >= bytes
+            }
+            GcTrigger::Occupancy(percentage) => {
+                let capacity = (self.offset(self.end) * size_of::<u64>())
+                    .min(self.quota.load(Ordering::Acquire));
+                used * 100 >= capacity * percentage as usize
+            }
+        }
+    }
+
+    /// Same as `collect`, but only if the trigger set with `set_gc_trigger`
+    /// was reached, returns whether it collected. Meant to be called at 
+    /// points where all live pointers are either in the roots or rooted
+    /// otherwise.
+    pub fn collect_if_needed(&self, roots: &mut impl Trace) -> bool {
+        let needed = self.should_collect();
+        if needed {
+            self.collect(roots);
+        }
+        needed
+    }
+
+    /// Garbage collect with the given pointers as roots, requires
+    /// exclusive access to the roots.
+    ///
+    /// Allocations on other threads wait for the collection to finish,
+    /// but the pointers those threads hold are not roots, so they are
+    /// invalid after the collection just like the unrooted ones of this
+    /// thread.
+    ///
+    /// Live chunks are compacted towards the start of the memory and
+    /// the roots are updated to point to their new location. Any other
+    /// pointer into the memory is invalid after the collection, unless
+    /// it was rooted on the shadow stack of this thread (see `root!`) or 
+    /// found by conservative stack scanning (see 
+    /// `Memory::with_conservative_roots`), in which case the chunk it 
+    /// points to is not moved.
+    pub fn collect(&self, roots: &mut impl Trace) {
+        let _closed = self.close_for_collection();
+        let region = Region { 
+            from: self.frozen_top.load(Ordering::Acquire), 
+            top: self.free_pointer.load(Ordering::Acquire) 
+        };
+        let (shadow, pinned) = self.implicit_roots(region.top);
+        self.collect_region(&mut (roots, shadow), &pinned, region, true);
+    }
+
+    /// Same as `collect`, but only collects the chunks allocated since the
+    /// previous collection, the ones that survive are promoted to the old
+    /// generation.
+    ///
+    /// Old chunks are assumed to be alive. Only the ones modified through
+    /// `MemPtr::cast_mut` (or `modify`, `tail_slice_mut`) since the previous
+    /// collection are traced for pointers to young chunks, so weak references
+    /// held by old chunks are only cleared by a full collection.
+    pub fn collect_minor(&self, roots: &mut impl Trace) {
+        let _closed = self.close_for_collection();
+        let region = Region { 
+            from: self.old_top.load(Ordering::Acquire), 
+            top: self.free_pointer.load(Ordering::Acquire) 
+        };
+        let (shadow, pinned) = self.implicit_roots(region.top);
+        self.collect_region(&mut (roots, shadow), &pinned, region, true);
+    }
+
+    /// Returns the roots on the shadow stack of this thread and the chunks 
+    /// pinned by conservative stack scanning, below the given top.
+    #[cfg(feature = "std")]
+    fn implicit_roots(&self, top: *mut u64) -> (ShadowRoots, Vec<*mut u64>) {
+        (ShadowRoots(self.start as usize), self.stack_roots(top))
+    }
+
+    /// Without `std` there are no threads to keep a shadow stack for, 
+    /// nor a stack to scan, so there are no implicit roots.
+    #[cfg(not(feature = "std"))]
+    fn implicit_roots(&self, _top: *mut u64) -> ((), Vec<*mut u64>) {
+        ((), Vec::new())
+    }
+
+    /// Without `std` no other thread scans its stack, see the `std` version
+    #[cfg(not(feature = "std"))]
+    fn close_for_collection(&self) -> super::sync::Closed<'_> {
+        self.gate.close()
+    }
+
+    /// Collects with the given roots, without moving the pinned chunks 
+    /// (sorted by address), as if they were found by stack scanning.
+    #[cfg(test)]
+    pub(super) fn collect_pinned(&self, roots: &mut impl Trace, pinned: &[*mut u64]) {
+        let _closed = self.gate.close();
+        let region = Region { 
+            from: self.frozen_top.load(Ordering::Acquire), 
+            top: self.free_pointer.load(Ordering::Acquire) 
+        };
+        self.collect_region(roots, pinned, region, true);
+    }
+
+    /// Runs `f` with the memory as an arena: when it returns, every chunk 
+    /// allocated in the meantime is freed at once, unless it is reachable 
+    /// from the result of `f`, from a root on the shadow stack or from a 
+    /// chunk allocated before (through `MemPtr::cast_mut` or `modify`). 
+    ///
+    /// Only the chunks of the arena are traced, which makes this much cheaper
+    /// than a collection when most of them are garbage. The chunks that 
+    /// survive are neither aged nor promoted. Nothing is freed if the memory
+    /// was collected or frozen while `f` ran.
+    pub fn with_arena<R: Trace>(&'t self, f: impl FnOnce(&'t Self) -> R) -> R {
+        let from = self.free_pointer.load(Ordering::Acquire);
+        let collections = self.collections.load(Ordering::Acquire);
+        let mut result = f(self);
+        let _closed = self.close_for_collection();
+        if self.collections.load(Ordering::Acquire) == collections && self.frozen_top.load(Ordering::Acquire) <= from {
+            let region = Region { from, top: self.free_pointer.load(Ordering::Acquire) };
+            let (shadow, pinned) = self.implicit_roots(region.top);
+            self.collect_region(&mut (&mut result, shadow), &pinned, region, false);
+        }
+        result
+    }
+
+    /// Freezes every chunk allocated so far, such as the constants and
+    /// procedures of a prelude. Allocations on other threads wait for it.
+    ///
+    /// Frozen chunks can only point to each other, so collections neither
+    /// trace nor move them, and they are never freed. Modifying them fails
+    /// (see `MemPtr::cast_mut`), which makes it safe to share them between
+    /// computations. Collect first so as not to freeze garbage.
+    pub fn freeze(&self) {
+        let _closed = self.gate.close();
+        let region = Region { 
+            from: self.frozen_top.load(Ordering::Acquire), 
+            top: self.free_pointer.load(Ordering::Acquire) 
+        };
+        for (chunk, _) in region.chunks() {
+            // the header is never written again once the chunk is frozen,
+            // so its identity hash has to be stored now
+            <MemPtr>::identity_hash(&MemPtr { ptr: chunk, pd: core::marker::PhantomData });
+            unsafe {
+                // SAFETY: the chunk is in use
+                let hdr = &mut *(chunk as *mut Header);
+                *hdr = hdr.with_frozen(true).with_remembered(false);
+            }
+        }
+        self.frozen_top.store(region.top, Ordering::Release);
+        self.old_top.store(region.top, Ordering::Release);
+    }
+
+    /// Collects the chunks in the region, the ones below it all survive.
+    /// If `promote` is set, the survivors become part of the old generation,
+    /// otherwise the generations are left as they are. The gate must be
+    /// closed, so that no allocation runs while chunks are moved.
+    fn collect_region(&self, roots: &mut impl Trace, pinned: &[*mut u64], region: Region, promote: bool) {
+        if self.canaries.load(Ordering::Acquire) {
+            // moving chunks over a corrupted heap would only spread the damage
+            if let Err(error) = self.check_canaries() {
+                panic!("{}", error);
+            }
+        }
+        let roots = &mut (roots, &mut *self.interned.lock().unwrap());
+        let used = self.offset(region.top);
+        self.notify(GcEvent::Started { minor: region.from != self.frozen_top.load(Ordering::Acquire), used });
+        let remembered = self.remembered(region);
+        let live = self.mark(roots, &remembered, pinned, region);
+        self.notify(GcEvent::Marked { live });
+        self.finalize(region);
+        let (forwarding, holes) = self.forwarding(pinned, region);
+        self.update(roots, &remembered, &forwarding, region);
+        let top = self.slide(&forwarding, region, promote);
+        self.fill(&holes);
+        self.poison(top, self.offset(region.top) - self.offset(top));
+        self.rehash(&remembered, Region { from: region.from, top });
+        self.free_pointer.store(top, Ordering::Release);
+        if promote {
+            // the remembered chunks can no longer point to young chunks
+            for chunk in remembered {
+                unsafe {
+                    // SAFETY: remembered chunks are in use and were not moved
+                    (*(chunk as *mut Header)).set_remembered(false);
+                }
+            }
+            self.old_top.store(top, Ordering::Release);
+            self.collections.fetch_add(1, Ordering::AcqRel);
+        }
+        self.notify(GcEvent::Swept { reclaimed: used - self.offset(top), used: self.offset(top) });
+    }
+}
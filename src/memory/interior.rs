@@ -0,0 +1,104 @@
+use core::ops::Range;
+
+use anyhow::{anyhow, Result};
+
+use super::{Bytes, ChunkContent, MemPtr, Trace};
+
+/// A pointer to part of the payload of a chunk, such as a substring or a
+/// subvector, that shares the cells of the chunk instead of copying them.
+///
+/// It consists of a pointer to the chunk it is derived from, its base, and
+/// the location of the part relative to the start of that chunk. Tracing
+/// it traces the base, so the base stays alive as long as the interior
+/// pointer is reachable and the collector can move the chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interior<'t> {
+    base: MemPtr<'t>,
+    /// Start of the part, in bytes after the header of the base
+    offset: usize,
+    /// Length of the part in bytes
+    len: usize
+}
+
+impl<'t, C> MemPtr<'t, C> {
+    /// Returns an interior pointer to the given cells of the tail of
+    /// a chunk of type `T` (see `tail_slice`).
+    pub fn interior<T: ChunkContent>(&self, range: Range<usize>) -> Result<Interior<'t>> {
+        let (_, len) = self.tail::<T>()?;
+        check(&range, len)?;
+        let fixed = T::size().unsigned_abs();
+        Ok(Interior {
+            base: self.clone().upcast(),
+            offset: (fixed + range.start) * size_of::<u64>(),
+            len: range.len() * size_of::<u64>()
+        })
+    }
+
+    /// Returns an interior pointer to the given bytes of a `Bytes` chunk
+    /// (see `as_bytes`).
+    pub fn substring(&self, range: Range<usize>) -> Result<Interior<'t>> {
+        let len = self.as_bytes()?.len();
+        check(&range, len)?;
+        let fixed = Bytes::size().unsigned_abs();
+        Ok(Interior { base: self.clone().upcast(), offset: fixed * size_of::<u64>() + range.start, len: range.len() })
+    }
+}
+
+fn check(range: &Range<usize>, len: usize) -> Result<()> {
+    if range.start > range.end || range.end > len {
+        return Err(anyhow!("range {:?} out of bounds for a payload of length {}", range, len));
+    }
+    Ok(())
+}
+
+impl<'t> Interior<'t> {
+    /// Returns the chunk the pointer is derived from
+    pub fn base(&self) -> &MemPtr<'t> {
+        &self.base
+    }
+
+    /// Returns the length of the part in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bytes of the part
+    pub fn as_bytes(&'t self) -> Result<&'t [u8]> {
+        let start = self.start()?;
+        unsafe {
+            // SAFETY: the part was checked to lie within the payload of the
+            // base when the pointer was derived, and chunks never shrink.
+            Ok(core::slice::from_raw_parts(start, self.len))
+        }
+    }
+
+    /// Returns the cells of the part, fails if it is
+    /// not made up of whole cells (see `MemPtr::interior`).
+    pub fn as_cells(&'t self) -> Result<&'t [u64]> {
+        let start = self.start()?;
+        if !self.offset.is_multiple_of(size_of::<u64>()) || !self.len.is_multiple_of(size_of::<u64>()) {
+            return Err(anyhow!("interior pointer is not made up of whole cells"));
+        }
+        unsafe {
+            // SAFETY: see `as_bytes`, the start is aligned to a cell
+            Ok(core::slice::from_raw_parts(start as *const u64, self.len / size_of::<u64>()))
+        }
+    }
+
+    /// Returns the first byte of the part
+    fn start(&self) -> Result<*const u8> {
+        self.base.header()?;
+        // the part starts after the header, within the same chunk
+        Ok((self.base.ptr as *const u8).wrapping_add(size_of::<u64>() + self.offset))
+    }
+}
+
+impl Trace for Interior<'_> {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        self.base.trace(visitor)
+    }
+}
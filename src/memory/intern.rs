@@ -0,0 +1,48 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use anyhow::Result;
+
+use super::{Backing, Memory, MemPtr, Trace};
+
+/// The chunks interned in a memory, by name
+#[derive(Default)]
+pub(super) struct Interned(BTreeMap<Vec<u8>, MemPtr<'static>>);
+
+impl Interned {
+    /// Forgets every interned chunk
+    pub(super) fn clear(&mut self) {
+        self.0.clear()
+    }
+}
+
+impl Trace for Interned {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        for ptr in self.0.values_mut() {
+            visitor(ptr)
+        }
+    }
+}
+
+impl<'t, B: Backing> Memory<'t, B> {
+    /// Returns the chunk interned under the given name, allocating it with
+    /// `make` if there is none yet, so that every chunk interned under the
+    /// same name is the same chunk, such as the symbols of a program.
+    ///
+    /// Interned chunks are roots of every collection: they stay alive,
+    /// and keep the chunks they point to alive, as long as the memory.
+    pub fn intern(&'t self, name: &[u8], make: impl FnOnce(&'t Self) -> Result<MemPtr<'t>>) -> Result<MemPtr<'t>> {
+        if let Some(ptr) = self.interned.lock().unwrap().0.get(name) {
+            return Ok(MemPtr { ptr: ptr.ptr, pd: core::marker::PhantomData });
+        }
+        // `make` may intern other names itself
+        let made = make(self)?;
+        let mut interned = self.interned.lock().unwrap();
+        let ptr = interned.0.entry(name.to_vec()).or_insert(MemPtr { ptr: made.ptr, pd: core::marker::PhantomData });
+        Ok(MemPtr { ptr: ptr.ptr, pd: core::marker::PhantomData })
+    }
+
+    /// Returns the chunk interned under the given name, if any
+    pub fn interned(&'t self, name: &[u8]) -> Option<MemPtr<'t>> {
+        self.interned.lock().unwrap().0.get(name).map(|ptr| MemPtr { ptr: ptr.ptr, pd: core::marker::PhantomData })
+    }
+}
@@ -0,0 +1,90 @@
+use core::ffi::{c_int, c_long, c_void};
+
+use anyhow::{anyhow, Result};
+
+use super::Backing;
+
+const PROT_NONE: c_int = 0;
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_PRIVATE: c_int = 2;
+#[cfg(target_os = "linux")]
+const MAP_ANONYMOUS: c_int = 0x20;
+#[cfg(target_os = "macos")]
+const MAP_ANONYMOUS: c_int = 0x1000;
+#[cfg(target_os = "linux")]
+const SC_PAGESIZE: c_int = 30;
+#[cfg(target_os = "macos")]
+const SC_PAGESIZE: c_int = 29;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+    fn sysconf(name: c_int) -> c_long;
+}
+
+/// An anonymous memory mapping surrounded by guard pages, so that 
+/// running off either end of the heap faults instead of silently 
+/// corrupting neighbouring memory.
+pub struct Mmap {
+    /// Start of the whole mapping (including the leading guard page)
+    mapping: *mut c_void,
+    /// Length of the whole mapping in bytes
+    mapping_len: usize,
+    /// Number of usable cells after the leading guard page
+    len: usize,
+}
+
+// SAFETY: the mapping is exclusively owned by this value.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    /// Map a fresh region with room for at least the given number of cells
+    pub fn new(cells: usize) -> Result<Mmap> {
+        unsafe {
+            // SAFETY: querying the page size has no preconditions
+            let page = sysconf(SC_PAGESIZE) as usize;
+            let usable = (cells * size_of::<u64>()).div_ceil(page).max(1) * page;
+            let mapping_len = usable + 2 * page;
+            // SAFETY: an anonymous mapping does not alias any existing memory, 
+            // it starts out inaccessible and only the inner pages are opened up.
+            let mapping = mmap(core::ptr::null_mut(), mapping_len, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            if mapping as isize == -1 {
+                return Err(anyhow!("could not map {} bytes", mapping_len));
+            }
+            if mprotect(mapping.byte_add(page), usable, PROT_READ | PROT_WRITE) != 0 {
+                munmap(mapping, mapping_len);
+                return Err(anyhow!("could not unprotect {} bytes", usable));
+            }
+            Ok(Mmap { mapping, mapping_len, len: usable / size_of::<u64>() })
+        }
+    }
+
+    fn page_size(&self) -> usize {
+        (self.mapping_len - self.len * size_of::<u64>()) / 2
+    }
+}
+
+// SAFETY: the mapping is only released when the `Mmap` is dropped and 
+// is unaffected by moving the `Mmap` value.
+unsafe impl Backing for Mmap {
+    fn cells(&mut self) -> &mut [u64] {
+        unsafe {
+            // SAFETY: the inner pages are readable, writable and zero-initialized 
+            // by the kernel, and exclusively borrowed through `self`.
+            let start = self.mapping.byte_add(self.page_size()) as *mut u64;
+            core::slice::from_raw_parts_mut(start, self.len)
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            // SAFETY: the mapping was created by `Mmap::new` and is not used afterwards
+            munmap(self.mapping, self.mapping_len);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::HashMap, fmt, panic::Location, sync::Mutex};
+
+use super::{Backing, Memory};
+
+/// Allocations made from a single call site
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Site {
+    pub location: &'static Location<'static>,
+    /// Number of chunks allocated
+    pub count: usize,
+    /// Size of those chunks in bytes, including their headers
+    pub bytes: usize
+}
+
+/// Allocations per call site, most bytes first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile(pub Vec<Site>);
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>12} {:>10}  site", "bytes", "count")?;
+        for site in &self.0 {
+            writeln!(f, "{:>12} {:>10}  {}", site.bytes, site.count, site.location)?;
+        }
+        Ok(())
+    }
+}
+
+/// Count and size of the allocations of every call site so far
+#[derive(Default)]
+pub(super) struct Sites(Mutex<HashMap<&'static Location<'static>, (usize, usize)>>);
+
+impl Sites {
+    pub(super) fn record(&self, location: &'static Location<'static>, cells: usize) {
+        let mut sites = self.0.lock().unwrap();
+        let (count, bytes) = sites.entry(location).or_default();
+        *count += 1;
+        *bytes += cells * size_of::<u64>();
+    }
+}
+
+impl<B: Backing> Memory<'_, B> {
+    /// Returns the number and size of the chunks allocated from each call
+    /// site so far. Allocations are attributed to the code calling the
+    /// `allocate` methods of the memory, not to the methods themselves.
+    pub fn allocation_profile(&self) -> Profile {
+        let mut sites: Vec<Site> = self.sites.0.lock().unwrap().iter()
+            .map(|(&location, &(count, bytes))| Site { location, count, bytes })
+            .collect();
+        sites.sort_by(|a, b| b.bytes.cmp(&a.bytes)
+            .then_with(|| (a.location.file(), a.location.line()).cmp(&(b.location.file(), b.location.line()))));
+        Profile(sites)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{ChunkContent, Header, Memory};
+
+    struct Number {
+        _hdr: Header,
+        _n: u64
+    }
+
+    impl ChunkContent for Number {
+        fn size() -> isize { 1 }
+        fn tag() -> usize { 2 }
+    }
+
+    #[test]
+    fn test_allocation_profile() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        for _ in 0..3 {
+            mem.allocate_raw::<Number>(0).unwrap();
+        }
+        let line = line!() - 2;
+        mem.allocate_bytes(40).unwrap();
+
+        let profile = mem.allocation_profile();
+        assert!(profile.0.len() == 2);
+        let (bytes, numbers) = (profile.0[0], profile.0[1]);
+        assert!(numbers.count == 3 && numbers.bytes == 3 * 2 * 8);
+        assert!(numbers.location.file() == file!() && numbers.location.line() == line);
+        assert!(bytes.count == 1 && bytes.bytes == 7 * 8);
+        assert!(bytes.location.line() == line + 3);
+        assert!(profile.to_string().lines().count() == 3);
+    }
+}
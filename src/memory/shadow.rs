@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+
+use super::{Backing, Memory, MemPtr, Trace};
+
+thread_local! {
+    /// The pointers rooted on this thread, with the start 
+    /// of the memory they were rooted in.
+    static SHADOW_STACK: RefCell<Vec<(usize, *mut MemPtr<'static>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A pointer variable registered as a root on the shadow stack of the 
+/// current thread, until the `Root` is dropped. Usually created through 
+/// the `root!` macro.
+///
+/// Collections on this thread update the variable in place when the 
+/// chunk it points to is moved.
+pub struct Root {
+    entry: (usize, *mut MemPtr<'static>)
+}
+
+impl Root {
+    /// Registers the variable as a root of the given memory, prefer `root!`.
+    ///
+    /// # Safety
+    ///
+    /// Collections write to the variable through the root, so the variable
+    /// must stay in place and in scope until the root is dropped, and the
+    /// root must be dropped, in reverse order of the roots created after
+    /// it on this thread. `root!` guarantees all of this by rooting local
+    /// variables only, with roots that cannot be named.
+    pub unsafe fn new<'t, B: Backing>(mem: &Memory<'t, B>, ptr: &mut MemPtr<'t>) -> Root {
+        let entry = (mem.start as usize, (ptr as *mut MemPtr<'t>).cast::<MemPtr<'static>>());
+        SHADOW_STACK.with_borrow_mut(|stack| stack.push(entry));
+        Root { entry }
+    }
+}
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        // the entry is removed even when it is not the last one, so that
+        // the roots dropped while unwinding do not panic again
+        let last = SHADOW_STACK.with_borrow_mut(|stack| {
+            match stack.iter().rposition(|&entry| entry == self.entry) {
+                Some(i) => {
+                    stack.remove(i);
+                    i == stack.len()
+                }
+                None => false
+            }
+        });
+        assert!(last || std::thread::panicking(), "roots must be dropped in reverse order");
+    }
+}
+
+/// The roots of a memory on the shadow stack of the current thread
+pub(super) struct ShadowRoots(pub(super) usize);
+
+impl Trace for ShadowRoots {
+    fn trace(&mut self, visitor: &mut impl FnMut(&mut MemPtr<'_>)) {
+        SHADOW_STACK.with_borrow(|stack| {
+            for &(owner, ptr) in stack {
+                if owner == self.0 {
+                    unsafe {
+                        // SAFETY: the variable is still in scope, since its 
+                        // `Root` has not been dropped yet.
+                        visitor(&mut *ptr)
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Roots local pointer variables of a memory on the shadow stack of the current 
+/// thread until the end of the enclosing block, so that collections keep the 
+/// chunks they point to alive and update the variables when those chunks move:
+///
+/// ```ignore
+/// let mut pair = mem.allocate::<Pair>(0)?;
+/// root!(mem, pair);
+/// mem.collect(&mut ());
+/// // `pair` still points to the pair
+/// ```
+#[macro_export]
+macro_rules! root {
+    ($mem:expr, $($ptr:ident),+ $(,)?) => {
+        $(let (mem, ptr) = (&$mem, &mut $ptr);
+        let _root = unsafe {
+            // SAFETY: `ptr` is a local variable that outlives `_root`, which
+            // cannot be named, so it is dropped in order at the end of the block
+            $crate::memory::Root::new(mem, ptr)
+        };)+
+    };
+}
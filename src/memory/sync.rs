@@ -0,0 +1,175 @@
+//! The locks of a memory. With `std` these are the ones of the standard
+//! library, without it they spin, which is fine for the short critical
+//! sections of the memory on targets without threads to park.
+
+use core::{hint::spin_loop, sync::atomic::{AtomicUsize, Ordering}};
+
+#[cfg(feature = "std")]
+pub(super) use std::sync::{Mutex, OnceLock};
+
+/// Set in the state of a `Gate` while it is closed
+const CLOSED: usize = 1 << (usize::BITS - 1);
+
+/// Lets allocations run alongside each other, but not alongside a
+/// collection: allocations enter the gate, a collection closes it. The
+/// state is the number of allocations that entered, with `CLOSED` set
+/// while a collection runs.
+pub(super) struct Gate(AtomicUsize);
+
+impl Gate {
+    pub(super) const fn new() -> Gate {
+        Gate(AtomicUsize::new(0))
+    }
+
+    /// Enters as an allocation, waits while the gate is closed
+    pub(super) fn enter(&self) -> Entered<'_> {
+        let mut state = self.0.load(Ordering::Acquire);
+        loop {
+            if state & CLOSED != 0 {
+                spin_loop();
+                state = self.0.load(Ordering::Acquire);
+                continue;
+            }
+            match self.0.compare_exchange_weak(state, state + 1, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => return Entered(self),
+                Err(current) => state = current
+            }
+        }
+    }
+
+    /// Closes the gate for a collection, waits for the collections
+    /// that closed it before and the allocations that entered it
+    pub(super) fn close(&self) -> Closed<'_> {
+        while self.0.fetch_or(CLOSED, Ordering::Acquire) & CLOSED != 0 {
+            spin_loop();
+        }
+        while self.0.load(Ordering::Acquire) != CLOSED {
+            spin_loop();
+        }
+        Closed(self)
+    }
+}
+
+/// An allocation that entered a `Gate`, leaves it when dropped
+pub(super) struct Entered<'g>(&'g Gate);
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        self.0.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A `Gate` closed for a collection, opens it again when dropped,
+/// also when the collection panics
+pub(super) struct Closed<'g>(&'g Gate);
+
+impl Drop for Closed<'_> {
+    fn drop(&mut self) {
+        self.0.0.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(super) use spin::{Mutex, OnceLock};
+
+#[cfg(not(feature = "std"))]
+mod spin {
+    use core::{cell::UnsafeCell, convert::Infallible, hint::spin_loop, mem::MaybeUninit, ops::{Deref, DerefMut}, sync::atomic::{AtomicBool, AtomicU8, Ordering}};
+
+    /// A spin lock with the interface of `std::sync::Mutex`,
+    /// locking it never fails.
+    pub struct Mutex<T> {
+        locked: AtomicBool,
+        value: UnsafeCell<T>
+    }
+
+    // SAFETY: the value is only accessed by the holder of the lock
+    unsafe impl<T: Send> Send for Mutex<T> {}
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    impl<T> Mutex<T> {
+        pub const fn new(value: T) -> Mutex<T> {
+            Mutex { locked: AtomicBool::new(false), value: UnsafeCell::new(value) }
+        }
+
+        pub fn lock(&self) -> Result<MutexGuard<'_, T>, Infallible> {
+            while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+                spin_loop();
+            }
+            Ok(MutexGuard(self))
+        }
+    }
+
+    pub struct MutexGuard<'m, T>(&'m Mutex<T>);
+
+    impl<T> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            // SAFETY: the guard holds the lock
+            unsafe { &*self.0.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            // SAFETY: the guard holds the lock
+            unsafe { &mut *self.0.value.get() }
+        }
+    }
+
+    impl<T> Drop for MutexGuard<'_, T> {
+        fn drop(&mut self) {
+            self.0.locked.store(false, Ordering::Release);
+        }
+    }
+
+    const EMPTY: u8 = 0;
+    const BUSY: u8 = 1;
+    const READY: u8 = 2;
+
+    /// A cell that is written once, with the interface of `std::sync::OnceLock`
+    pub struct OnceLock<T> {
+        state: AtomicU8,
+        value: UnsafeCell<MaybeUninit<T>>
+    }
+
+    // SAFETY: the value is written once, before `state` becomes `READY`,
+    // and only read after
+    unsafe impl<T: Send> Send for OnceLock<T> {}
+    unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+
+    impl<T> OnceLock<T> {
+        pub const fn new() -> OnceLock<T> {
+            OnceLock { state: AtomicU8::new(EMPTY), value: UnsafeCell::new(MaybeUninit::uninit()) }
+        }
+
+        pub fn get(&self) -> Option<&T> {
+            // SAFETY: the value is initialized once the state is `READY`
+            (self.state.load(Ordering::Acquire) == READY).then(|| unsafe { (*self.value.get()).assume_init_ref() })
+        }
+
+        pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+            if self.state.compare_exchange(EMPTY, BUSY, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+                // SAFETY: setting the state to `BUSY` gives exclusive access
+                unsafe { (*self.value.get()).write(f()) };
+                self.state.store(READY, Ordering::Release);
+            }
+            loop {
+                if let Some(value) = self.get() {
+                    return value;
+                }
+                spin_loop();
+            }
+        }
+    }
+
+    impl<T> Drop for OnceLock<T> {
+        fn drop(&mut self) {
+            if *self.state.get_mut() == READY {
+                // SAFETY: the value is initialized and dropped only once
+                unsafe { self.value.get_mut().assume_init_drop() }
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use core::ptr::without_provenance;
+
+use alloc::vec::Vec;
+
+use anyhow::{anyhow, Result};
+
+use super::{Backing, ChunkContent, Header, Memory, MemPtr, RESERVED_TAGS};
+
+/// Key of a removed entry, lookups keep probing past it
+const TOMBSTONE: usize = 0b110;
+
+/// A hash table keyed by pointer identity whose entries are removed by 
+/// the collector once their key is no longer reachable from elsewhere. 
+/// Like for an `Ephemeron`, the value of an entry does not keep its key alive.
+///
+/// The entries are stored in the tail of the chunk as key/value pairs, 
+/// hashed by address, and rehashed after every collection.
+pub struct WeakTable {
+    _hdr: Header,
+    len: u64
+}
+
+impl ChunkContent for WeakTable {
+    fn size() -> isize { 1 }
+    fn tag() -> usize { RESERVED_TAGS + 3 }
+}
+
+fn is_free(key: &MemPtr<'_>) -> bool {
+    key.is_null() || key.ptr.addr() == TOMBSTONE
+}
+
+fn hash(key: &MemPtr<'_>) -> usize {
+    (key.ptr.addr() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15).rotate_left(32) as usize
+}
+
+/// Calls the visitor with every key and value of a weak table
+///
+/// # Safety
+///
+/// `chunk` must point to an initialized weak table.
+pub(super) unsafe fn trace_table(chunk: *mut u64, visitor: &mut dyn FnMut(&mut MemPtr<'_>)) {
+    let (slots, len) = entries(chunk);
+    for i in 0..len * 2 {
+        if !is_free(&*slots.add(i & !1)) {
+            visitor(&mut *slots.add(i));
+        }
+    }
+}
+
+/// Returns the first key of the entries of a weak table, and the number of entries
+///
+/// # Safety
+///
+/// `chunk` must point to an initialized weak table.
+unsafe fn entries(chunk: *mut u64) -> (*mut MemPtr<'static>, usize) {
+    let hdr = *(chunk as *const Header);
+    (chunk.add(1 + WeakTable::size() as usize) as *mut MemPtr<'static>, (hdr.size() - WeakTable::size() as usize) / 2)
+}
+
+/// Calls the visitor with the key and value of every entry of a weak table
+///
+/// # Safety
+///
+/// `chunk` must point to an initialized weak table.
+pub(super) unsafe fn weak_entries(chunk: *mut u64, visitor: &mut impl FnMut(*mut MemPtr<'static>, *mut MemPtr<'static>)) {
+    let (slots, len) = entries(chunk);
+    for i in 0..len {
+        let key = slots.add(2 * i);
+        if !is_free(&*key) {
+            visitor(key, key.add(1));
+        }
+    }
+}
+
+/// Re-inserts all entries of a weak table whose chunk has been moved, dropping
+/// the entries that were cleared by the collector.
+///
+/// # Safety
+///
+/// `chunk` must point to an initialized weak table.
+pub(super) unsafe fn rehash(chunk: *mut u64) {
+    let (slots, len) = entries(chunk);
+    let mut live = Vec::new();
+    for i in 0..len {
+        let key = &mut *slots.add(2 * i);
+        let value = &mut *slots.add(2 * i + 1);
+        if !is_free(key) {
+            live.push((key.clone(), value.clone()));
+        }
+        *key = MemPtr::null();
+        *value = MemPtr::null();
+    }
+    (*(chunk as *mut WeakTable)).len = 0;
+    let table = MemPtr { ptr: chunk, pd: core::marker::PhantomData };
+    for (key, value) in live {
+        WeakTable::insert(&table, key, value).expect("rehashing cannot overflow the table");
+    }
+}
+
+impl WeakTable {
+    /// Number of entries in the table
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns true if the table has no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the entries of the table, checking that it is one
+    fn slots<'t>(table: &MemPtr<'t>) -> Result<&'t mut [MemPtr<'t>]> {
+        table.tail::<WeakTable>()?;
+        unsafe {
+            // SAFETY: the tag was checked above
+            let (slots, len) = entries(table.ptr as *mut u64);
+            Ok(core::slice::from_raw_parts_mut(slots.cast::<MemPtr<'t>>(), 2 * len))
+        }
+    }
+
+    /// Finds the slot of the given key, or the slot it can be inserted at
+    fn probe(slots: &[MemPtr<'_>], key: &MemPtr<'_>) -> (Option<usize>, Option<usize>) {
+        let len = slots.len() / 2;
+        let mut free = None;
+        for i in 0..len {
+            let slot = (hash(key).wrapping_add(i)) % len;
+            let candidate = &slots[2 * slot];
+            if candidate == key {
+                return (Some(slot), free);
+            } else if candidate.is_null() {
+                return (None, free.or(Some(slot)));
+            } else if is_free(candidate) && free.is_none() {
+                free = Some(slot);
+            }
+        }
+        (None, free)
+    }
+
+    /// Associates the value with the key, replacing the previous value if any.
+    /// Fails if the key is the null pointer or if the table is full.
+    pub fn insert<'t>(table: &MemPtr<'t>, key: MemPtr<'t>, value: MemPtr<'t>) -> Result<()> {
+        if key.is_null() {
+            return Err(anyhow!("the null pointer cannot be a key"));
+        }
+        table.writable()?;
+        let slots = WeakTable::slots(table)?;
+        match WeakTable::probe(slots, &key) {
+            (Some(slot), _) => slots[2 * slot + 1] = value,
+            (None, Some(slot)) if table.cast::<WeakTable>()?.len() < slots.len() / 2 / 4 => {
+                slots[2 * slot] = key;
+                slots[2 * slot + 1] = value;
+                table.modify::<WeakTable>(|table| table.len += 1);
+            },
+            _ => return Err(anyhow!("weak table is full")),
+        }
+        Ok(())
+    }
+
+    /// Returns the value associated with the key
+    pub fn get<'t>(table: &MemPtr<'t>, key: &MemPtr<'_>) -> Result<Option<MemPtr<'t>>> {
+        let slots = WeakTable::slots(table)?;
+        Ok(WeakTable::probe(slots, key).0.map(|slot| slots[2 * slot + 1].clone()))
+    }
+
+    /// Removes the entry of the key, returning its value
+    pub fn remove<'t>(table: &MemPtr<'t>, key: &MemPtr<'_>) -> Result<Option<MemPtr<'t>>> {
+        table.writable()?;
+        let slots = WeakTable::slots(table)?;
+        let Some(slot) = WeakTable::probe(slots, key).0 else { return Ok(None) };
+        slots[2 * slot] = MemPtr { ptr: without_provenance(TOMBSTONE), pd: core::marker::PhantomData };
+        let value = core::mem::take(&mut slots[2 * slot + 1]);
+        table.modify::<WeakTable>(|table| table.len -= 1);
+        Ok(Some(value))
+    }
+}
+
+impl<'t, B: Backing> Memory<'t, B> {
+    /// Allocate a weak table with room for the given number of entries
+    #[cfg_attr(feature = "profiling", track_caller)]
+    pub fn allocate_weak_table(&'t self, capacity: usize) -> Result<MemPtr<'t>> {
+        self.tracers[WeakTable::tag()].get_or_init(|| trace_table);
+        // keep the table at most a quarter full, so probe sequences remain short
+        let slots = isize::try_from(capacity.max(1).checked_mul(4 * 2)
+            .ok_or_else(|| anyhow!("weak table capacity too large"))?)?;
+        self.allocate_::<WeakTable>(slots, false)
+    }
+}
@@ -0,0 +1,303 @@
+//! The numeric tower. Numbers are stored as fixnums or `Number`s while they
+//! fit in an `i64`, as `Bignum`s when they do not, as `Rational`s when they
+//! are fractions, and as `Flonum`s when they are inexact. Arithmetic on exact
+//! numbers stays exact, promoting the result to the representation it needs
+//! and demoting it to the smallest one it fits in, see `Numeric::allocate`.
+//! An inexact operand makes the result inexact.
+
+use core::cmp::Ordering;
+
+use anyhow::{anyhow, Result};
+
+use crate::{grammar::{integer, rational, BigInt, Bignum, Flonum, Number, Rational}, match_heap, memory::{Backing, MemPtr, Memory}};
+
+/// The value of a number, however it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum Numeric {
+    /// A fixnum or a `Number`
+    Integer(i64),
+    /// A `Bignum`
+    Big(BigInt),
+    /// A `Rational`, as its numerator and denominator
+    Ratio(BigInt, BigInt),
+    /// A `Flonum`
+    Float(f64)
+}
+
+impl Numeric {
+    /// Returns the fraction in lowest terms, with the sign in the numerator,
+    /// as an integer if the denominator divides the numerator
+    pub fn ratio(numerator: &BigInt, denominator: &BigInt) -> Result<Numeric> {
+        if denominator.is_zero() {
+            return Err(anyhow!("division by zero"));
+        }
+        // the gcd is not zero, as the denominator is not
+        let gcd = numerator.gcd(denominator);
+        let (n, d) = (numerator.div_rem(&gcd).unwrap().0, denominator.div_rem(&gcd).unwrap().0);
+        let (n, d) = if d.is_negative() { (n.neg(), d.neg()) } else { (n, d) };
+        Ok(if d == BigInt::from(1) { Numeric::Big(n) } else { Numeric::Ratio(n, d) })
+    }
+
+    /// Returns the number as a float, which may round integers and fractions
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Numeric::Integer(n) => *n as f64,
+            Numeric::Big(n) => n.to_f64(),
+            Numeric::Ratio(n, d) => n.to_f64() / d.to_f64(),
+            Numeric::Float(f) => *f
+        }
+    }
+
+    /// Returns the number as an integer of any size, if it is an integer
+    pub fn as_big(&self) -> Option<BigInt> {
+        match self {
+            Numeric::Integer(n) => Some(BigInt::from(*n)),
+            Numeric::Big(n) => Some(n.clone()),
+            Numeric::Ratio(..) | Numeric::Float(_) => None
+        }
+    }
+
+    /// Returns the numerator and denominator of the number, if it is exact
+    pub fn as_ratio(&self) -> Option<(BigInt, BigInt)> {
+        match self {
+            Numeric::Ratio(n, d) => Some((n.clone(), d.clone())),
+            n => Some((n.as_big()?, BigInt::from(1)))
+        }
+    }
+
+    /// Stores the number, see `integer`, `Bignum::new`, `rational` and `Flonum::new`.
+    /// Integers are stored in the smallest representation they fit in.
+    pub fn allocate<'t, B: Backing>(&self, mem: &'t Memory<'t, B>) -> Result<MemPtr<'t>> {
+        match self {
+            Numeric::Integer(n) => integer(mem, *n),
+            Numeric::Big(n) => match n.to_i64() {
+                Some(n) => integer(mem, n),
+                None => Ok(Bignum::new(mem, n)?.upcast())
+            },
+            Numeric::Ratio(n, d) => rational(mem, n, d),
+            Numeric::Float(f) => Ok(Flonum::new(mem, *f)?.upcast())
+        }
+    }
+}
+
+impl<C> MemPtr<'_, C> {
+    /// Returns the value of the number the pointer holds or points to, if any
+    pub fn as_numeric(&self) -> Option<Numeric> {
+        if let Some(n) = self.as_fixnum() {
+            return Some(Numeric::Integer(n));
+        }
+        let ptr = self.clone().upcast();
+        match_heap!(ptr, {
+            Number(number) => Some(Numeric::Integer(number.n)),
+            Bignum(bignum) => bignum.value().ok().map(Numeric::Big),
+            Rational(ratio) => ratio.value().ok().map(|(n, d)| Numeric::Ratio(n, d)),
+            Flonum(flonum) => Some(Numeric::Float(flonum.f)),
+            _ => None
+        })
+    }
+}
+
+/// A fraction as its numerator and denominator
+type Ratio = (BigInt, BigInt);
+
+/// The operations on each kind of number, see `arithmetic`
+struct Operation {
+    /// Fails if the result is not an `i64`
+    integers: fn(i64, i64) -> Option<i64>,
+    /// Returns a fraction that is not necessarily in lowest terms
+    ratios: fn(&Ratio, &Ratio) -> Ratio,
+    floats: fn(f64, f64) -> f64
+}
+
+/// Returns the values of both numbers, fails if either is not a number
+fn operands(a: &MemPtr<'_>, b: &MemPtr<'_>) -> Result<(Numeric, Numeric)> {
+    match (a.as_numeric(), b.as_numeric()) {
+        (Some(x), Some(y)) => Ok((x, y)),
+        (None, _) => Err(anyhow!("not a number: {:?}", a)),
+        (_, None) => Err(anyhow!("not a number: {:?}", b))
+    }
+}
+
+/// Applies the integer operation if both numbers are fixnums or `Number`s
+/// and the result fits, the operation on fractions if both are exact, and
+/// the float operation if either of them is a float.
+fn arithmetic<'t, B: Backing>(mem: &'t Memory<'t, B>, a: &MemPtr<'_>, b: &MemPtr<'_>, op: Operation) -> Result<MemPtr<'t>> {
+    let (x, y) = operands(a, b)?;
+    if let (Numeric::Integer(x), Numeric::Integer(y)) = (&x, &y) {
+        if let Some(n) = (op.integers)(*x, *y) {
+            return integer(mem, n);
+        }
+    }
+    match (x.as_ratio(), y.as_ratio()) {
+        (Some(x), Some(y)) => {
+            let (n, d) = (op.ratios)(&x, &y);
+            Numeric::ratio(&n, &d)?.allocate(mem)
+        },
+        _ => Numeric::Float((op.floats)(x.as_f64(), y.as_f64())).allocate(mem)
+    }
+}
+
+/// Adds two numbers, the sum is a float if either of them is
+pub fn add<'t, B: Backing>(mem: &'t Memory<'t, B>, a: &MemPtr<'_>, b: &MemPtr<'_>) -> Result<MemPtr<'t>> {
+    arithmetic(mem, a, b, Operation {
+        integers: i64::checked_add,
+        ratios: |(a, b), (c, d)| (a.mul(d).add(&c.mul(b)), b.mul(d)),
+        floats: |x, y| x + y
+    })
+}
+
+/// Subtracts two numbers, the difference is a float if either of them is
+pub fn sub<'t, B: Backing>(mem: &'t Memory<'t, B>, a: &MemPtr<'_>, b: &MemPtr<'_>) -> Result<MemPtr<'t>> {
+    arithmetic(mem, a, b, Operation {
+        integers: i64::checked_sub,
+        ratios: |(a, b), (c, d)| (a.mul(d).sub(&c.mul(b)), b.mul(d)),
+        floats: |x, y| x - y
+    })
+}
+
+/// Multiplies two numbers, the product is a float if either of them is
+pub fn mul<'t, B: Backing>(mem: &'t Memory<'t, B>, a: &MemPtr<'_>, b: &MemPtr<'_>) -> Result<MemPtr<'t>> {
+    arithmetic(mem, a, b, Operation {
+        integers: i64::checked_mul,
+        ratios: |(a, b), (c, d)| (a.mul(c), b.mul(d)),
+        floats: |x, y| x * y
+    })
+}
+
+/// Divides two numbers, the quotient of exact numbers is exact, 
+/// a fraction if needed. Fails on an exact division by zero.
+pub fn div<'t, B: Backing>(mem: &'t Memory<'t, B>, a: &MemPtr<'_>, b: &MemPtr<'_>) -> Result<MemPtr<'t>> {
+    arithmetic(mem, a, b, Operation {
+        integers: |x, y| x.checked_rem(y).filter(|r| *r == 0).and_then(|_| x.checked_div(y)),
+        ratios: |(a, b), (c, d)| (a.mul(d), b.mul(c)),
+        floats: |x, y| x / y
+    })
+}
+
+
+/// Compares two numbers, exactly if both of them are exact. 
+/// They are unordered if either of them is a NaN.
+pub fn cmp(a: &MemPtr<'_>, b: &MemPtr<'_>) -> Result<Option<Ordering>> {
+    Ok(match operands(a, b)? {
+        (Numeric::Integer(x), Numeric::Integer(y)) => Some(x.cmp(&y)),
+        (x, y) => match (x.as_ratio(), y.as_ratio()) {
+            // denominators are positive, so cross multiplying keeps the order
+            (Some((a, b)), Some((c, d))) => Some(a.mul(&d).cmp(&c.mul(&b))),
+            _ => x.as_f64().partial_cmp(&y.as_f64())
+        }
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_flonums() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let half = Flonum::new(&mem, 0.5).unwrap();
+        assert!(half.f == 0.5 && half.as_numeric() == Some(Numeric::Float(0.5)));
+        let one = <MemPtr>::fixnum(1).unwrap();
+        let big = integer(&mem, i64::MAX).unwrap();
+        assert!(one.as_numeric() == Some(Numeric::Integer(1)));
+        assert!(big.as_numeric() == Some(Numeric::Integer(i64::MAX)));
+        assert!(MemPtr::null().as_numeric().is_none());
+
+        // integers stay integers, floats are contagious
+        let half = half.upcast();
+        assert!(add(&mem, &one, &one).unwrap().as_fixnum() == Some(2));
+        assert!(add(&mem, &one, &half).unwrap().as_numeric() == Some(Numeric::Float(1.5)));
+        assert!(sub(&mem, &half, &one).unwrap().as_numeric() == Some(Numeric::Float(-0.5)));
+        assert!(mul(&mem, &big, &half).unwrap().as_numeric() == Some(Numeric::Float(i64::MAX as f64 / 2.0)));
+        assert!(sub(&mem, &big, &big).unwrap().as_fixnum() == Some(0));
+        assert!(add(&mem, &big, &one).unwrap().as_numeric().unwrap().as_f64() == 2f64.powi(63));
+        assert!(add(&mem, &one, &MemPtr::null()).is_err());
+    }
+
+    #[test]
+    fn test_bignums() {
+        let mut data: [u64 ; 1000] = [ 0 ; 1000 ];
+        let mem = Memory::new(&mut data);
+        let mut factorial = <MemPtr>::fixnum(1).unwrap();
+        for n in 1..=30 {
+            factorial = mul(&mem, &factorial, &<MemPtr>::fixnum(n).unwrap()).unwrap();
+        }
+        let Some(Numeric::Big(value)) = factorial.as_numeric() else { panic!("not a bignum") };
+        assert!(value.to_string() == "265252859812191058636308480000000");
+        assert!(value.neg().to_string() == "-265252859812191058636308480000000");
+
+        // bignums shrink back to fixnums when they can
+        let back = (1..=30).try_fold(factorial.clone(), |n, d| {
+            let Some(Numeric::Big(n)) = n.as_numeric() else { return sub(&mem, &n, &<MemPtr>::fixnum(d).unwrap()) };
+            Numeric::Big(n.sub(&BigInt::from(d))).allocate(&mem)
+        }).unwrap();
+        assert!(back.as_numeric().unwrap().as_big().unwrap() == value.sub(&BigInt::from(465)));
+        let zero = sub(&mem, &factorial, &factorial).unwrap();
+        assert!(zero.as_fixnum() == Some(0));
+        let min = sub(&mem, &integer(&mem, i64::MIN).unwrap(), &<MemPtr>::fixnum(1).unwrap()).unwrap();
+        assert!(min.as_numeric() == Some(Numeric::Big(BigInt::from(i64::MIN).sub(&BigInt::from(1)))));
+        assert!(add(&mem, &min, &<MemPtr>::fixnum(1).unwrap()).unwrap().as_numeric() == Some(Numeric::Integer(i64::MIN)));
+        assert!(BigInt::from(i64::MIN).to_i64() == Some(i64::MIN) && BigInt::from(0).to_string() == "0");
+        let float = add(&mem, &factorial, &Flonum::new(&mem, 0.5).unwrap().upcast()).unwrap();
+        assert!(float.as_numeric() == Some(Numeric::Float(value.to_f64() + 0.5)));
+    }
+
+    #[test]
+    fn test_cmp() {
+        let mut data: [u64 ; 100] = [ 0 ; 100 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let third = div(&mem, &n(1), &n(3)).unwrap();
+        let big = mul(&mem, &integer(&mem, i64::MAX).unwrap(), &n(2)).unwrap();
+        let nan = Flonum::new(&mem, f64::NAN).unwrap().upcast();
+        let ascending = [integer(&mem, i64::MIN).unwrap(), n(-1), n(0), third.clone(), Flonum::new(&mem, 0.5).unwrap().upcast(), n(1), big];
+        for (i, a) in ascending.iter().enumerate() {
+            for (j, b) in ascending.iter().enumerate() {
+                assert!(cmp(a, b).unwrap() == Some(i.cmp(&j)));
+            }
+            assert!(cmp(a, &nan).unwrap().is_none());
+        }
+        // exactly, where floats would round them to the same value
+        let close = add(&mem, &third, &div(&mem, &n(1), &integer(&mem, i64::MAX).unwrap()).unwrap()).unwrap();
+        assert!(cmp(&third, &close).unwrap() == Some(Ordering::Less));
+        assert!(cmp(&n(1), &Flonum::new(&mem, 1.0).unwrap().upcast()).unwrap() == Some(Ordering::Equal));
+        assert!(cmp(&n(1), &MemPtr::null()).is_err());
+    }
+
+    #[test]
+    fn test_rationals() {
+        let mut data: [u64 ; 1000] = [ 0 ; 1000 ];
+        let mem = Memory::new(&mut data);
+        let n = |n: i64| <MemPtr>::fixnum(n).unwrap();
+        let ratio = |n: i64, d: i64| Some(Numeric::Ratio(BigInt::from(n), BigInt::from(d)));
+        let third = div(&mem, &n(1), &n(3)).unwrap();
+        assert!(third.downcast::<Rational>().is_ok() && third.as_numeric() == ratio(1, 3));
+        assert!(div(&mem, &n(6), &n(-4)).unwrap().as_numeric() == ratio(-3, 2));
+        assert!(div(&mem, &n(-6), &n(-3)).unwrap().as_fixnum() == Some(2));
+        assert!(div(&mem, &n(0), &n(5)).unwrap().as_fixnum() == Some(0));
+        assert!(div(&mem, &n(1), &n(0)).is_err());
+        assert!(div(&mem, &Flonum::new(&mem, 1.0).unwrap().upcast(), &n(4)).unwrap().as_numeric() == Some(Numeric::Float(0.25)));
+
+        // fractions stay exact, and become integers again when they can
+        let two_thirds = add(&mem, &third, &third).unwrap();
+        assert!(two_thirds.as_numeric() == ratio(2, 3));
+        assert!(add(&mem, &two_thirds, &third).unwrap().as_fixnum() == Some(1));
+        assert!(sub(&mem, &third, &n(1)).unwrap().as_numeric() == ratio(-2, 3));
+        assert!(mul(&mem, &third, &n(3)).unwrap().as_fixnum() == Some(1));
+        assert!(div(&mem, &third, &two_thirds).unwrap().as_numeric() == ratio(1, 2));
+        assert!(mul(&mem, &third, &Flonum::new(&mem, 1.5).unwrap().upcast()).unwrap().as_numeric() == Some(Numeric::Float(0.5)));
+        assert!(div(&mem, &integer(&mem, i64::MIN).unwrap(), &n(-1)).unwrap().as_numeric().unwrap().as_big() == Some(BigInt::from(i64::MIN).neg()));
+
+        // with bignums on either side
+        let big = mul(&mem, &integer(&mem, i64::MAX).unwrap(), &n(4)).unwrap();
+        let quarter = div(&mem, &n(1), &big).unwrap();
+        assert!(mul(&mem, &quarter, &big).unwrap().as_fixnum() == Some(1));
+        assert!(div(&mem, &big, &n(4)).unwrap().as_numeric() == Some(Numeric::Integer(i64::MAX)));
+        let (q, r) = BigInt::from(-7).div_rem(&BigInt::from(2)).unwrap();
+        assert!(q == BigInt::from(-3) && r == BigInt::from(-1));
+        assert!(BigInt::from(12).gcd(&BigInt::from(-18)) == BigInt::from(6));
+    }
+
+}
@@ -0,0 +1,312 @@
+//! Printing values as s-expressions
+
+use core::fmt;
+
+use alloc::string::{String, ToString};
+
+use crate::{grammar::{ListIter, Pair, RecordType, Symbol, Value}, memory::MemPtr};
+
+/// The characters that are written by name
+const CHAR_NAMES: [(char, &str); 9] = [
+    ('\x07', "alarm"), ('\x08', "backspace"), ('\x7f', "delete"), ('\x1b', "escape"), 
+    ('\n', "newline"), ('\0', "null"), ('\r', "return"), (' ', "space"), ('\t', "tab")
+];
+
+/// Displays the value a pointer points to, as it would be written in a program.
+/// A value inside of itself, such as the rest of a circular list, is written
+/// as `#<cycle>`, so every value can be printed, also in error messages.
+pub struct Printer<'a, 't>(pub &'a MemPtr<'t>);
+
+impl fmt::Display for Printer<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Nested { ptr: self.0, enclosing: None }.fmt(f)
+    }
+}
+
+/// A value being printed, with the values it is printed inside of
+struct Nested<'a, 't> {
+    ptr: &'a MemPtr<'t>,
+    enclosing: Option<&'a Nested<'a, 't>>
+}
+
+impl<'t> Nested<'_, 't> {
+    /// A value printed inside of this one
+    fn inside<'b>(&'b self, ptr: &'b MemPtr<'t>) -> Nested<'b, 't> {
+        Nested { ptr, enclosing: Some(self) }
+    }
+
+    /// Returns true if the value is printed inside of itself
+    fn is_cycle(&self) -> bool {
+        let mut enclosing = self.enclosing;
+        while let Some(outer) = enclosing {
+            if outer.ptr == self.ptr {
+                return true;
+            }
+            enclosing = outer.enclosing;
+        }
+        false
+    }
+}
+
+/// Why a value could not be written
+enum Failure {
+    /// The formatter failed
+    Write(fmt::Error),
+    /// The value is malformed, such as a string that is not UTF-8
+    Invalid
+}
+
+impl From<fmt::Error> for Failure {
+    fn from(error: fmt::Error) -> Failure {
+        Failure::Write(error)
+    }
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(_: anyhow::Error) -> Failure {
+        Failure::Invalid
+    }
+}
+
+impl fmt::Display for Nested<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.write(f) {
+            Ok(()) => Ok(()),
+            Err(Failure::Write(error)) => Err(error),
+            // written where the value would have been, so that printing never fails
+            Err(Failure::Invalid) => write!(f, "#<invalid>")
+        }
+    }
+}
+
+impl Nested<'_, '_> {
+    fn write(&self, f: &mut fmt::Formatter<'_>) -> Result<(), Failure> {
+        let ptr = self.ptr;
+        if self.is_cycle() {
+            return Ok(write!(f, "#<cycle>")?);
+        }
+        Ok(match ptr.classify() {
+            Value::Null => write!(f, "()"),
+            Value::Fixnum(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", if b { "#t" } else { "#f" }),
+            Value::Eof => write!(f, "#<eof>"),
+            Value::Unspecified => write!(f, "#<unspecified>"),
+            Value::Char(c) => match CHAR_NAMES.iter().find(|(named, _)| *named == c) {
+                Some((_, name)) => write!(f, "#\\{}", name),
+                None if c.is_control() => write!(f, "#\\x{:x}", c as u32),
+                None => write!(f, "#\\{}", c)
+            },
+            Value::Pair(mut pair) => {
+                write!(f, "({}", self.inside(&pair.car))?;
+                // moves at half the speed of `pair`, as in `ListIter`,
+                // the rest of the list is circular if they ever meet
+                let (mut slow, mut steps) = (pair.clone(), 0usize);
+                loop {
+                    match pair.cdr.downcast::<Pair>() {
+                        Ok(next) => {
+                            pair = next;
+                            steps += 1;
+                            if steps.is_multiple_of(2) {
+                                slow = slow.cdr.downcast::<Pair>()?;
+                                if slow == pair {
+                                    return Ok(write!(f, " . #<cycle>)")?);
+                                }
+                            }
+                            write!(f, " {}", self.inside(&pair.car))?;
+                        }
+                        Err(_) if pair.cdr.is_null() => return Ok(write!(f, ")")?),
+                        Err(_) => return Ok(write!(f, " . {})", self.inside(&pair.cdr))?)
+                    }
+                }
+            },
+            Value::Vector(vector) => {
+                write!(f, "#(")?;
+                for (i, slot) in vector.slots()?.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { " " };
+                    match slot.get() {
+                        Some(value) => write!(f, "{}{}", separator, self.inside(value))?,
+                        None => write!(f, "{}#<empty>", separator)?
+                    }
+                }
+                write!(f, ")")
+            },
+            Value::Bytevector(bytevector) => {
+                write!(f, "#u8(")?;
+                for (i, byte) in bytevector.bytes()?.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { "" } else { " " }, byte)?;
+                }
+                write!(f, ")")
+            },
+            Value::Number(number) => write!(f, "{}", number.n),
+            Value::Bignum(bignum) => write!(f, "{}", bignum.value()?),
+            Value::Rational(ratio) => write!(f, "{}/{}", self.inside(&ratio.numerator), self.inside(&ratio.denominator)),
+            Value::Flonum(flonum) => match flonum.f {
+                x if x.is_nan() => write!(f, "+nan.0"),
+                f64::INFINITY => write!(f, "+inf.0"),
+                f64::NEG_INFINITY => write!(f, "-inf.0"),
+                x => write!(f, "{:?}", x)
+            },
+            Value::Closure(_) => write!(f, "#<procedure>"),
+            Value::Code(code) => match code.name.downcast::<Symbol>() {
+                Ok(name) => write!(f, "#<code {}>", name.name()?),
+                Err(_) => write!(f, "#<code>")
+            },
+            Value::Condition(condition) => {
+                write!(f, "#<condition {}: {}", condition.kind().name(), condition.message()?)?;
+                for irritant in ListIter::new(condition.irritants.clone()) {
+                    write!(f, " {}", self.inside(&irritant?))?;
+                }
+                write!(f, ">")
+            },
+            Value::Continuation(_) => write!(f, "#<continuation>"),
+            Value::Environment(_) => write!(f, "#<environment>"),
+            Value::HashTable(table) => write!(f, "#<hash-table {}>", table.len()),
+            Value::MBox(cell) => write!(f, "#&{}", self.inside(&cell.unbox())),
+            // not the value, which may contain the promise itself
+            Value::Promise(_) => write!(f, "#<promise>"),
+            Value::Port(port) => match (port.is_input(), port.is_open()) {
+                (true, true) => write!(f, "#<input-port>"),
+                (false, true) => write!(f, "#<output-port>"),
+                (_, false) => write!(f, "#<closed-port>")
+            },
+            Value::RecordType(record_type) => write!(f, "#<record-type {}>", self.inside(&record_type.name)),
+            // not the fields, which may contain the record itself
+            Value::Record(record) => match record.record_type.downcast::<RecordType>() {
+                Ok(record_type) => write!(f, "#<{}>", self.inside(&record_type.name)),
+                Err(_) => write!(f, "#<record>")
+            },
+            Value::Str(str) => write_string(f, str.as_str()?),
+            Value::Symbol(symbol) if symbol.is_interned() => write!(f, "{}", symbol.name()?),
+            Value::Symbol(symbol) => write!(f, "#{{{}}}", symbol.name()?),
+            Value::Keyword(keyword) => write!(f, "#:{}", keyword.name()?),
+            Value::Syntax(syntax) => write!(f, "#<syntax {} {}>", syntax.location()?, self.inside(&syntax.datum)),
+            // not a value of the language, but the contents of strings and names
+            Value::Bytes(bytes) => {
+                write!(f, "#<bytes")?;
+                for byte in bytes.as_bytes()? {
+                    write!(f, " {}", byte)?;
+                }
+                write!(f, ">")
+            },
+            Value::Other(_) => write!(f, "#<chunk {}>", ptr.tag()?)
+        }?)
+    }
+}
+
+/// Writes the string between double quotes, escaped as in R7RS
+fn write_string(f: &mut fmt::Formatter<'_>, str: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in str.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\x07' => write!(f, "\\a")?,
+            '\x08' => write!(f, "\\b")?,
+            '\t' => write!(f, "\\t")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            c if c.is_control() => write!(f, "\\x{:x};", c as u32)?,
+            c => write!(f, "{}", c)?
+        }
+    }
+    write!(f, "\"")
+}
+
+/// Returns the printed representation of the value
+pub fn print(ptr: &MemPtr<'_>) -> String {
+    Printer(ptr).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::grammar::{character, integer, list, Arity, Bytevector, Closure, Code, Comparator, Condition, ConditionKind, Flonum, HashTable, Keyword, MBox, Pair, Port, Promise, Record, RecordType, Str, Symbol, Syntax, Vector};
+    use crate::numeric::div;
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_print() {
+        let mut data: [u64 ; 300] = [ 0 ; 300 ];
+        let mem = Memory::new(&mut data);
+        let numbers = (1..=3).map(|n| <MemPtr>::fixnum(n).unwrap()).collect::<Vec<_>>();
+        assert!(print(&MemPtr::null()) == "()");
+        assert!(print(&list(&mem, numbers.clone()).unwrap()) == "(1 2 3)");
+        let dotted = Pair::new(&mem, numbers[0].clone(), numbers[1].clone()).unwrap().upcast();
+        assert!(print(&dotted) == "(1 . 2)");
+        let big = integer(&mem, i64::MAX).unwrap();
+        assert!(print(&list(&mem, [dotted, big]).unwrap()) == format!("((1 . 2) {})", i64::MAX));
+        assert!(print(&mem.allocate_bytes(2).unwrap()) == "#<bytes 0 0>");
+        let quote = list(&mem, [Symbol::new(&mem, "quote").unwrap().upcast(), MemPtr::null()]).unwrap();
+        assert!(print(&quote) == "(quote ())");
+        let singletons = list(&mem, [mem.true_(), mem.false_(), mem.nil(), mem.eof(), mem.unspecified()]).unwrap();
+        assert!(print(&singletons) == "(#t #f () #<eof> #<unspecified>)");
+        let chars = list(&mem, ['a', 'λ', '\n', ' ', '\x01'].map(character)).unwrap();
+        let floats = [0.5, 1.0, -2e100, f64::NAN, f64::INFINITY, f64::NEG_INFINITY].map(|x| Flonum::new(&mem, x).unwrap().upcast());
+        assert!(print(&list(&mem, floats).unwrap()) == "(0.5 1.0 -2e100 +nan.0 +inf.0 -inf.0)");
+        assert!(print(&chars) == "(#\\a #\\λ #\\newline #\\space #\\x1)");
+        let vector = Vector::make(&mem, 3, None).unwrap();
+        assert!(print(&vector.clone().upcast()) == "#(#<empty> #<empty> #<empty>)");
+        vector.set(0, quote).unwrap();
+        vector.set(2, Vector::make(&mem, 0, None).unwrap().upcast()).unwrap();
+        assert!(print(&vector.upcast()) == "#((quote ()) #<empty> #())");
+        assert!(print(&Bytevector::new(&mem, &[0, 7, 255]).unwrap().upcast()) == "#u8(0 7 255)");
+        assert!(print(&Bytevector::new(&mem, &[]).unwrap().upcast()) == "#u8()");
+        let table = HashTable::new(&mem, Comparator::Eq).unwrap();
+        table.insert(&mem, numbers[0].clone(), MemPtr::null()).unwrap();
+        assert!(print(&table.upcast()) == "#<hash-table 1>");
+        let closure = Closure::new(&mem, MemPtr::null(), MemPtr::null(), Arity { required: 0, rest: true }).unwrap();
+        assert!(print(&closure.upcast()) == "#<procedure>");
+        let code = Code::new(&mem, Symbol::new(&mem, "f").unwrap().upcast(), &[0], &[]).unwrap();
+        assert!(print(&code.upcast()) == "#<code f>");
+        let promise = Promise::delay(&mem, MemPtr::null()).unwrap();
+        let cycle = list(&mem, [promise.clone().upcast()]).unwrap();
+        promise.force(|_| Ok(cycle.clone())).unwrap();
+        assert!(print(&cycle) == "(#<promise>)");
+        let point = RecordType::new(&mem, Symbol::new(&mem, "point").unwrap(), &[Symbol::new(&mem, "x").unwrap()]).unwrap();
+        let record = Record::new(&mem, &point, &[numbers[0].clone()]).unwrap();
+        record.set_field(0, record.clone().upcast()).unwrap();
+        assert!(print(&point.upcast()) == "#<record-type point>" && print(&record.upcast()) == "#<point>");
+        let condition = Condition::new(&mem, ConditionKind::Range, "index out of range:", &numbers[..1]).unwrap();
+        assert!(print(&condition.upcast()) == "#<condition range: index out of range: 1>");
+        let syntax = Syntax::new(&mem, numbers[0].clone(), MemPtr::null(), 1, 1).unwrap();
+        assert!(print(&syntax.upcast()) == "#<syntax 1:1 1>");
+        assert!(print(&Keyword::new(&mem, "key").unwrap().upcast()) == "#:key");
+        assert!(print(&MBox::new(&mem, numbers[0].clone()).unwrap().upcast()) == "#&1");
+        let port = Port::output_string(&mem).unwrap();
+        assert!(print(&Port::input_string(&mem, "").unwrap().upcast()) == "#<input-port>" && print(&port.clone().upcast()) == "#<output-port>");
+        port.close().unwrap();
+        assert!(print(&port.upcast()) == "#<closed-port>");
+        let third = div(&mem, &numbers[0], &<MemPtr>::fixnum(-3).unwrap()).unwrap();
+        assert!(print(&third) == "-1/3");
+        assert!(print(&Str::new(&mem, "say \"hi\"").unwrap().upcast()) == "\"say \\\"hi\\\"\"");
+        assert!(print(&Str::new(&mem, "a\\b\tλ\x01\x7f\n").unwrap().upcast()) == "\"a\\\\b\\tλ\\x1;\\x7f;\\n\"");
+        // malformed values are written as such, instead of failing
+        let invalid = mem.allocate_byte_chunk::<Str>(1).unwrap();
+        invalid.tail_bytes_mut::<Str>().unwrap()[0] = 0xff;
+        assert!(print(&list(&mem, [invalid, numbers[0].clone()]).unwrap()) == "(#<invalid> 1)");
+    }
+
+    #[test]
+    fn test_print_cycles() {
+        let mut data: [u64 ; 200] = [ 0 ; 200 ];
+        let mem = Memory::new(&mut data);
+        let numbers = (1..=3).map(|n| <MemPtr>::fixnum(n).unwrap()).collect::<Vec<_>>();
+        let circular = list(&mem, numbers.clone()).unwrap();
+        let last = circular.cdr().unwrap().cdr().unwrap();
+        last.modify::<Pair>(|pair| pair.cdr = circular.clone());
+        // the cycle is noticed within a second round
+        assert!(print(&circular) == "(1 2 3 1 2 3 . #<cycle>)");
+        let inside = list(&mem, numbers.clone()).unwrap();
+        inside.modify::<Pair>(|pair| pair.car = inside.clone());
+        assert!(print(&inside) == "(#<cycle> 2 3)");
+        let vector = Vector::make(&mem, 2, Some(numbers[0].clone())).unwrap();
+        vector.set(1, list(&mem, [vector.clone().upcast()]).unwrap()).unwrap();
+        assert!(print(&vector.upcast()) == "#(1 (#<cycle>))");
+        let cell = MBox::new(&mem, MemPtr::null()).unwrap();
+        cell.set(cell.clone().upcast()).unwrap();
+        assert!(print(&cell.upcast()) == "#&#<cycle>");
+        // shared, but not circular
+        let shared = list(&mem, numbers[..1].iter().cloned()).unwrap();
+        assert!(print(&list(&mem, [shared.clone(), shared]).unwrap()) == "((1) (1))");
+    }
+}
@@ -0,0 +1,56 @@
+use core::fmt;
+
+use alloc::vec::Vec;
+
+/// What exactly is running, for bug reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    /// Semantic version of the crate
+    pub version: &'static str,
+    /// Commit the binary was built from, "unknown" outside of a git checkout
+    pub git_hash: &'static str,
+    /// Cargo features the binary was built with
+    pub features: Vec<&'static str>,
+    /// Size of a memory cell and of a pointer, in bits
+    pub word_size: usize
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "slip {} ({}, {}-bit", self.version, self.git_hash, self.word_size)?;
+        for feature in &self.features {
+            write!(f, ", {}", feature)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Returns the version and build metadata of this binary
+pub fn version() -> Version {
+    let features = [
+        ("std", cfg!(feature = "std")),
+        ("profiling", cfg!(feature = "profiling")),
+    ];
+    Version {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("SLIP_GIT_HASH"),
+        features: features.into_iter().filter(|(_, on)| *on).map(|(name, _)| name).collect(),
+        word_size: usize::BITS as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let version = version();
+        assert!(version.version == "0.1.0");
+        assert!(!version.git_hash.is_empty());
+        assert!(version.word_size == 64);
+        assert!(version.features.contains(&"std") == cfg!(feature = "std"));
+        assert!(version.features.contains(&"profiling") == cfg!(feature = "profiling"));
+        assert!(version.to_string().starts_with("slip 0.1.0 ("));
+    }
+}